@@ -1,19 +1,107 @@
-use ratatui::widgets::ListState;
+use std::{
+    cmp::min,
+    collections::HashSet,
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Error, Result};
+use ratatui::{layout::Rect, widgets::ListState};
 use secular::lower_lay_string;
+use serde::{de, Deserialize, Deserializer};
 
-use crate::mpd::{Status, Track};
+use crate::{
+    cmdline::Editor,
+    config::View,
+    mpd::{
+        expand, filter_expr, quote, Client, Entry, MpdError, Output, PlayerState, Status, Track,
+    },
+};
 
 pub struct State {
     pub status: Status,
+    pub status_time: Instant,
     pub queue: Vec<Track>,
     pub selected: usize,
     pub liststate: ListState,
     pub searching: bool,
     pub query: String,
     pub filtered: Vec<usize>,
+    pub focus: View,
+    pub browser: Browser,
+    pub library: Library,
+    pub search: Search,
+    pub playlists: Browser, // the stored playlists, or the songs of the opened one
+    pub prompt: Option<Prompt>,
+    pub history: Vec<String>, // the lines run from the command line
+    pub pending_keys: String, // the count and the keys typed so far of an incomplete binding
+    pub outputs: List<Output>,
+    pub marked: HashSet<u32>,
+    pub visual: Option<u32>, // the id of the song visual mode started on
+    pub connected: bool,
+    pub message: Option<Message>,
+    pub message_timeout: Duration,
+    pub progress_areas: Vec<Rect>,
+}
+
+/// A list that is not the queue, with its own selection
+pub struct List<T> {
+    pub items: Vec<T>,
+    pub selected: usize,
+    pub liststate: ListState,
+}
+
+pub struct Browser {
+    pub path: String,
+    pub list: List<Entry>,
+    // the selected rows of the parent directories
+    history: Vec<usize>,
+}
+
+/// Songs browsed by tags, each level of the hierarchy is listed by the selections before it
+pub struct Library {
+    pub groups: Vec<List<Vec<(String, String)>>>,
+    pub tracks: List<Track>,
+    pub active: usize, // the focused level, the tracks if it equals the number of groups
+    // the selected rows that the levels after them were loaded for
+    loaded: Vec<usize>,
+}
+
+/// Songs found by searching the database
+pub struct Search {
+    pub query: String,
+    pub editing: bool,
+    pub expr: Option<String>, // the expression of the last search
+    pub list: List<Track>,
+}
+
+/// Text typed at the bottom of the screen to finish a command
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+}
+
+pub enum PromptKind {
+    SaveQueue,
+    AddToPlaylist(Vec<String>), // the songs or directories to add
+    RenamePlaylist(String),
+    DeletePlaylist(String),
+    CommandLine(Editor),
+}
+
+pub struct Message {
+    pub level: MessageLevel,
+    pub text: String,
+    pub expires: Instant,
+}
+
+#[derive(Eq, PartialEq)]
+pub enum MessageLevel {
+    Info,
+    Error,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize)]
 pub enum Command {
     Quit,
     ToggleRepeat,
@@ -25,6 +113,20 @@ pub enum Command {
     Stop,
     SeekBackwards,
     SeekForwards,
+    VolumeUp,
+    VolumeDown,
+    ToggleMute,
+    Delete,
+    MoveUp,
+    MoveDown,
+    Clear,
+    Shuffle,
+    Crop,
+    PriorityUp,
+    PriorityDown,
+    ToggleMark,
+    Visual,
+    ClearMarks,
     Previous,
     Next,
     Play,
@@ -35,11 +137,436 @@ pub enum Command {
     JumpUp,
     GotoTop,
     GotoBottom,
+    #[serde(skip)]
     InputSearch(char),
     BackspaceSearch,
     ClearSearch,
     QuitSearch,
     Searching(bool),
+    Click,
+    #[serde(skip)]
+    ClickAt(u16, u16),
+    #[serde(skip)]
+    PendingKeys(String),
+    FocusNext,
+    FocusPrevious,
+    Back,
+    Add,
+    Insert,
+    Replace,
+    AddAll,
+    ReplaceAll,
+    SearchLibrary,
+    SaveQueue,
+    AddToPlaylist,
+    RenamePlaylist,
+    Cmdline,
+    ToggleOutput,
+    SwitchOutput,
+    Mpd(#[serde(deserialize_with = "mpd_commands")] Vec<String>),
+}
+
+/// A value of a song that can be used in the commands of `Command::Mpd`
+enum Placeholder<'a> {
+    File,
+    Id,
+    Pos,
+    Tag(&'a str),
+    Filter(Vec<&'a str>),
+}
+
+/// Movement within the focused list
+pub trait Cursor {
+    fn selected(&self) -> usize;
+    fn len(&self) -> usize;
+    fn select(&mut self, x: usize);
+    fn reselect(&mut self);
+
+    fn down(&mut self, cycle: bool) {
+        let len = self.len();
+        let selected = self.selected();
+        if selected >= len {
+            self.reselect();
+        } else if selected == len - 1 {
+            if cycle {
+                self.select(0);
+            }
+        } else {
+            self.select(selected + 1);
+        }
+    }
+
+    fn up(&mut self, cycle: bool) {
+        let len = self.len();
+        let selected = self.selected();
+        if selected >= len {
+            self.reselect();
+        } else if selected == 0 {
+            if cycle {
+                self.select(len - 1);
+            }
+        } else {
+            self.select(selected - 1);
+        }
+    }
+
+    fn jump_down(&mut self, lines: usize, cycle: bool) {
+        let len = self.len();
+        let selected = self.selected();
+        if selected >= len {
+            self.reselect();
+        } else if cycle {
+            self.select((selected + lines % len) % len);
+        } else {
+            self.select(min(selected.saturating_add(lines), len - 1));
+        }
+    }
+
+    fn jump_up(&mut self, lines: usize, cycle: bool) {
+        let len = self.len();
+        let selected = self.selected();
+        if selected >= len {
+            self.reselect();
+        } else if cycle {
+            self.select((selected + len - lines % len) % len);
+        } else {
+            self.select(selected.saturating_sub(lines));
+        }
+    }
+
+    fn goto(&mut self, row: usize) {
+        let len = self.len();
+        if len != 0 {
+            self.select(min(row, len - 1));
+        }
+    }
+
+    fn goto_top(&mut self) {
+        self.select(0);
+    }
+
+    fn goto_bottom(&mut self) {
+        let len = self.len();
+        if len != 0 {
+            self.select(len - 1);
+        }
+    }
+}
+
+impl Command {
+    /// Whether the command only makes sense when the queue is focused
+    pub fn queue_only(&self) -> bool {
+        matches!(
+            self,
+            Command::MoveUp
+                | Command::MoveDown
+                | Command::PriorityUp
+                | Command::PriorityDown
+                | Command::ToggleMark
+                | Command::Visual
+                | Command::ClearMarks
+        )
+    }
+
+    /// Whether a count typed before the command repeats it
+    pub fn countable(&self) -> bool {
+        matches!(
+            self,
+            Command::Down
+                | Command::Up
+                | Command::JumpDown
+                | Command::JumpUp
+                | Command::GotoTop
+                | Command::GotoBottom
+                | Command::SeekBackwards
+                | Command::SeekForwards
+                | Command::Delete
+        )
+    }
+
+    /// Whether the command starts (true) or stops (false) reading text input,
+    /// None if it doesn't change the input mode
+    pub fn text_input(&self) -> Option<bool> {
+        match self {
+            Command::Searching(x) => Some(*x),
+            Command::SearchLibrary
+            | Command::SaveQueue
+            | Command::AddToPlaylist
+            | Command::RenamePlaylist
+            | Command::Cmdline => Some(true),
+            Command::QuitSearch => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl Placeholder<'_> {
+    /// Parses the name of a placeholder,
+    /// along with whether it refers to the current song instead of the selected song
+    fn parse(name: &str) -> Result<(bool, Placeholder<'_>), String> {
+        let (current, name) = match name.strip_prefix("current_") {
+            Some(name) => (true, name),
+            None => (false, name),
+        };
+        let placeholder = match name {
+            "file" => Placeholder::File,
+            "id" => Placeholder::Id,
+            "pos" => Placeholder::Pos,
+            _ => {
+                if let Some(tag) = name.strip_prefix("tag:") {
+                    Placeholder::Tag(tag)
+                } else if let Some(tags) = name.strip_prefix("filter:") {
+                    Placeholder::Filter(tags.split(',').collect())
+                } else {
+                    return Err(format!("unknown placeholder {{{name}}}"));
+                }
+            }
+        };
+        Ok((current, placeholder))
+    }
+}
+
+fn mpd_commands<'de, D: Deserializer<'de>>(de: D) -> Result<Vec<String>, D::Error> {
+    let cmds = Vec::<String>::deserialize(de)?;
+    for cmd in &cmds {
+        expand(cmd, |name| Placeholder::parse(name).map(|_| String::new()))
+            .map_err(de::Error::custom)?;
+    }
+    Ok(cmds)
+}
+
+impl<T> List<T> {
+    pub fn new() -> List<T> {
+        List {
+            items: Vec::new(),
+            selected: 0,
+            liststate: ListState::default(),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.items.get(self.selected)
+    }
+
+    /// Replaces the items, keeping the selection within bounds
+    pub fn set_items(&mut self, items: Vec<T>) {
+        self.items = items;
+        self.select(min(self.selected, self.items.len().saturating_sub(1)));
+    }
+}
+
+impl<T> Cursor for List<T> {
+    fn selected(&self) -> usize {
+        self.selected
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn select(&mut self, x: usize) {
+        self.selected = x;
+        self.liststate.select(Some(x));
+    }
+
+    fn reselect(&mut self) {
+        self.select(0);
+    }
+}
+
+impl Browser {
+    pub fn new() -> Browser {
+        Browser {
+            path: String::new(),
+            list: List::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Enters a subdirectory with its entries
+    pub fn enter(&mut self, path: String, entries: Vec<Entry>) {
+        self.history.push(self.list.selected);
+        self.path = path;
+        self.list.items = entries;
+        self.list.select(0);
+    }
+
+    /// The parent directory, None if already at the root
+    pub fn parent(&self) -> Option<String> {
+        if self.path.is_empty() {
+            None
+        } else {
+            Some(
+                self.path
+                    .rsplit_once('/')
+                    .map_or_else(String::new, |(parent, _)| parent.into()),
+            )
+        }
+    }
+
+    /// Goes back to the parent directory with its entries
+    pub fn leave(&mut self, path: String, entries: Vec<Entry>) {
+        self.path = path;
+        self.list.items = entries;
+        let selected = self.history.pop().unwrap_or_default();
+        self.list
+            .select(min(selected, self.list.items.len().saturating_sub(1)));
+    }
+
+    /// Goes back to the root with its entries, e.g. after the current directory was removed
+    pub fn reset(&mut self, entries: Vec<Entry>) {
+        self.history.clear();
+        self.path.clear();
+        self.list.items = entries;
+        self.list.select(0);
+    }
+}
+
+impl Library {
+    pub fn new(levels: usize) -> Library {
+        Library {
+            groups: (0 .. levels).map(|_| List::new()).collect(),
+            tracks: List::new(),
+            active: 0,
+            loaded: vec![0; levels],
+        }
+    }
+
+    pub fn focused(&mut self) -> &mut dyn Cursor {
+        if let Some(groups) = self.groups.get_mut(self.active) {
+            groups
+        } else {
+            &mut self.tracks
+        }
+    }
+
+    /// The tag values selected in the levels before `level`,
+    /// None if one of them has nothing to select
+    pub fn filter(&self, level: usize) -> Option<Vec<(String, String)>> {
+        let mut filter = Vec::new();
+        for groups in &self.groups[.. level] {
+            filter.extend_from_slice(groups.get()?);
+        }
+        Some(filter)
+    }
+
+    /// The first level that has to be reloaded because the selection before it changed
+    pub fn stale(&self) -> Option<usize> {
+        self.groups
+            .iter()
+            .zip(&self.loaded)
+            .position(|(groups, &loaded)| groups.selected != loaded)
+            .map(|i| i + 1)
+    }
+
+    /// Reloads the levels from `from` to the tracks,
+    /// the levels after `from` start from their first row
+    pub async fn load(
+        &mut self,
+        cl: &mut Client,
+        levels: &[Vec<String>],
+        from: usize,
+    ) -> Result<()> {
+        self.fetch(cl, levels, from, false).await
+    }
+
+    /// Reloads every level after the database changed, keeping the selections
+    pub async fn reload(&mut self, cl: &mut Client, levels: &[Vec<String>]) -> Result<()> {
+        self.fetch(cl, levels, 0, true).await
+    }
+
+    async fn fetch(
+        &mut self,
+        cl: &mut Client,
+        levels: &[Vec<String>],
+        from: usize,
+        keep: bool,
+    ) -> Result<()> {
+        let res = async {
+            for level in from ..= self.groups.len() {
+                let filter = self.filter(level);
+                if let Some(tags) = levels.get(level) {
+                    let rows = match filter {
+                        Some(filter) => cl.list(tags, &filter).await?,
+                        None => Vec::new(),
+                    };
+                    let groups = &mut self.groups[level];
+                    if keep || level == from {
+                        groups.set_items(rows);
+                    } else {
+                        groups.items = rows;
+                        groups.select(0);
+                    }
+                } else {
+                    let tracks = match filter {
+                        Some(filter) if !filter.is_empty() => cl.find(&filter).await?,
+                        _ => Vec::new(),
+                    };
+                    if keep || level == from {
+                        self.tracks.set_items(tracks);
+                    } else {
+                        self.tracks.items = tracks;
+                        self.tracks.select(0);
+                    }
+                }
+            }
+            Ok(())
+        }
+        .await;
+
+        // don't retry on every update if loading failed
+        self.loaded = self.groups.iter().map(|groups| groups.selected).collect();
+        res
+    }
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind) -> Prompt {
+        let input = match &kind {
+            PromptKind::RenamePlaylist(name) => name.clone(),
+            _ => String::new(),
+        };
+        Prompt { kind, input }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self.kind {
+            PromptKind::SaveQueue => "Save the queue as: ",
+            PromptKind::AddToPlaylist(_) => "Add to playlist: ",
+            PromptKind::RenamePlaylist(_) => "Rename the playlist to: ",
+            PromptKind::DeletePlaylist(_) => "Delete the playlist? (y/n) ",
+            PromptKind::CommandLine(_) => ":",
+        }
+    }
+}
+
+impl Search {
+    pub fn new() -> Search {
+        Search {
+            query: String::new(),
+            editing: false,
+            expr: None,
+            list: List::new(),
+        }
+    }
+}
+
+impl Cursor for State {
+    fn selected(&self) -> usize {
+        self.selected
+    }
+
+    fn len(&self) -> usize {
+        State::len(self)
+    }
+
+    fn select(&mut self, x: usize) {
+        State::select(self, x);
+    }
+
+    fn reselect(&mut self) {
+        State::reselect(self);
+    }
 }
 
 impl State {
@@ -52,6 +579,239 @@ impl State {
         self.select(self.status.song.as_ref().map_or(0, |song| song.pos));
     }
 
+    pub fn focused(&mut self) -> &mut dyn Cursor {
+        match self.focus {
+            View::Queue => self,
+            View::Browser => &mut self.browser.list,
+            View::Library => self.library.focused(),
+            View::Search => &mut self.search.list,
+            View::Playlists => &mut self.playlists.list,
+            View::Outputs => &mut self.outputs,
+        }
+    }
+
+    /// The selected entry of the browser or the playlists, whichever is focused
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        match self.focus {
+            View::Browser => self.browser.list.get(),
+            View::Playlists => self.playlists.list.get(),
+            _ => None,
+        }
+    }
+
+    /// The selected song of the focused view, with its position if it is in the queue
+    pub fn selected_track(&self) -> Option<(&Track, Option<usize>)> {
+        match self.focus {
+            View::Queue => {
+                let pos = self.selected_pos()?;
+                Some((&self.queue[pos], Some(pos)))
+            }
+            View::Browser | View::Playlists => match self.selected_entry()? {
+                Entry::Song(track) => Some((track, None)),
+                _ => None,
+            },
+            View::Library => self.library.tracks.get().map(|track| (track, None)),
+            View::Search => self.search.list.get().map(|track| (track, None)),
+            View::Outputs => None,
+        }
+    }
+
+    /// The value of a placeholder in the commands of `Command::Mpd`, quoted as an argument
+    pub fn placeholder(&self, name: &str) -> Result<String, String> {
+        let (current, placeholder) = Placeholder::parse(name)?;
+        let (track, pos) = if current {
+            (
+                self.current_track().ok_or("No song is playing")?,
+                self.status.song.as_ref().map(|song| song.pos),
+            )
+        } else {
+            self.selected_track().ok_or("No song is selected")?
+        };
+
+        Ok(match placeholder {
+            Placeholder::File => quote(&track.file),
+            Placeholder::Id => track.id.ok_or("The song is not in the queue")?.to_string(),
+            Placeholder::Pos => pos.ok_or("The song is not in the queue")?.to_string(),
+            Placeholder::Tag(tag) => quote(
+                track
+                    .tag(tag)
+                    .ok_or_else(|| format!("The song has no {tag} tag"))?,
+            ),
+            Placeholder::Filter(tags) => {
+                let tags: Vec<_> = tags
+                    .into_iter()
+                    .map(|tag| (tag.into(), track.tag(tag).unwrap_or_default().into()))
+                    .collect();
+                quote(&filter_expr(&tags))
+            }
+        })
+    }
+
+    pub fn update_status(&mut self, status: Status) {
+        self.status = status;
+        self.status_time = Instant::now();
+    }
+
+    /// Elapsed time of the current song, extrapolated from the last status
+    pub fn elapsed(&self) -> Option<f64> {
+        let song = self.status.song.as_ref()?;
+        if self.status.state == PlayerState::Play {
+            let elapsed = song.elapsed + self.status_time.elapsed().as_secs_f64();
+            Some(
+                song.duration
+                    .map_or(elapsed, |duration| elapsed.min(duration)),
+            )
+        } else {
+            Some(song.elapsed)
+        }
+    }
+
+    /// Duration of the current song, falling back to the duration of the track in the queue
+    pub fn duration(&self) -> Option<f64> {
+        self.status
+            .song
+            .as_ref()
+            .and_then(|song| song.duration)
+            .or_else(|| self.current_track()?.duration)
+    }
+
+    /// Position in the current song that a click on a progress bar at (x, y) points at
+    pub fn progress_position(&self, x: u16, y: u16) -> Option<f64> {
+        let area = self.progress_areas.iter().find(|area| {
+            (area.x .. area.x + area.width).contains(&x)
+                && (area.y .. area.y + area.height).contains(&y)
+        })?;
+        let ratio = (f64::from(x - area.x) + 0.5) / f64::from(area.width);
+        Some(self.duration()? * ratio)
+    }
+
+    pub fn current_track(&self) -> Option<&Track> {
+        let song = self.status.song.as_ref()?;
+        self.queue
+            .get(song.pos)
+            .filter(|track| track.id == Some(song.id))
+    }
+
+    /// The position in the queue of a row, which differs from the row when the queue is filtered
+    pub fn row_pos(&self, row: usize) -> Option<usize> {
+        if self.query.is_empty() {
+            (row < self.queue.len()).then_some(row)
+        } else {
+            self.filtered.get(row).copied()
+        }
+    }
+
+    pub fn selected_pos(&self) -> Option<usize> {
+        self.row_pos(self.selected)
+    }
+
+    /// The id of the selected track
+    pub fn selected_id(&self) -> Option<u32> {
+        self.queue.get(self.selected_pos()?)?.id
+    }
+
+    /// Rows between the row visual mode started on and the selected row
+    pub fn visual_rows(&self) -> Option<RangeInclusive<usize>> {
+        let id = self.visual?;
+        let row = if self.query.is_empty() {
+            self.queue.iter().position(|track| track.id == Some(id))?
+        } else {
+            self.filtered
+                .iter()
+                .position(|&i| self.queue[i].id == Some(id))?
+        };
+        Some(min(row, self.selected) ..= row.max(self.selected))
+    }
+
+    pub fn is_marked(&self, row: usize, visual_rows: Option<&RangeInclusive<usize>>) -> bool {
+        visual_rows.is_some_and(|rows| rows.contains(&row))
+            || self
+                .row_pos(row)
+                .and_then(|pos| self.queue[pos].id)
+                .is_some_and(|id| self.marked.contains(&id))
+    }
+
+    pub fn has_marks(&self) -> bool {
+        self.visual.is_some() || !self.marked.is_empty()
+    }
+
+    /// Positions in the queue to operate on, the marked songs if there are any,
+    /// otherwise the selected song
+    pub fn targets(&self) -> Vec<usize> {
+        if self.has_marks() {
+            let visual_rows = self.visual_rows();
+            (0 .. self.len())
+                .filter(|&row| self.is_marked(row, visual_rows.as_ref()))
+                .filter_map(|row| self.row_pos(row))
+                .collect()
+        } else {
+            self.selected_pos().into_iter().collect()
+        }
+    }
+
+    pub fn toggle_mark(&mut self) {
+        if let Some(id) = self.selected_id() {
+            if !self.marked.remove(&id) {
+                self.marked.insert(id);
+            }
+        }
+    }
+
+    /// Enters visual mode, or leaves it and marks the songs that were in the range
+    pub fn toggle_visual(&mut self) {
+        if let Some(rows) = self.visual_rows() {
+            for row in rows {
+                if let Some(id) = self.row_pos(row).and_then(|pos| self.queue[pos].id) {
+                    self.marked.insert(id);
+                }
+            }
+            self.visual = None;
+        } else {
+            self.visual = self.selected_id();
+        }
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.visual = None;
+    }
+
+    /// Refilters the queue after it changed and keeps the selection on the same track,
+    /// or on the same row if the track was removed
+    pub fn update_queue(&mut self, queue_strings: &[String], selected: Option<u32>) {
+        // forget the marks of songs that left the queue, they can't be operated on anymore
+        if !self.marked.is_empty() || self.visual.is_some() {
+            let ids: HashSet<_> = self.queue.iter().filter_map(|track| track.id).collect();
+            self.marked.retain(|id| ids.contains(id));
+            if self.visual.is_some_and(|id| !ids.contains(&id)) {
+                self.visual = None;
+            }
+        }
+
+        if !self.query.is_empty() {
+            self.filter(queue_strings);
+        }
+
+        let len = self.len();
+        let pos = selected.and_then(|id| {
+            if self.query.is_empty() {
+                self.queue.iter().position(|track| track.id == Some(id))
+            } else {
+                self.filtered
+                    .iter()
+                    .position(|&i| self.queue[i].id == Some(id))
+            }
+        });
+
+        if let Some(pos) = pos {
+            self.select(pos);
+        } else if selected.is_some() && len != 0 {
+            self.select(min(self.selected, len - 1));
+        } else {
+            self.reselect();
+        }
+    }
+
     pub fn len(&self) -> usize {
         if self.query.is_empty() {
             self.queue.len()
@@ -61,6 +821,12 @@ impl State {
     }
 
     pub fn update_search(&mut self, queue_strings: &[String]) {
+        self.filter(queue_strings);
+        self.liststate.select(None);
+        self.select(0);
+    }
+
+    fn filter(&mut self, queue_strings: &[String]) {
         let query = lower_lay_string(&self.query);
         self.filtered.clear();
         for (i, track) in queue_strings.iter().enumerate() {
@@ -68,13 +834,46 @@ impl State {
                 self.filtered.push(i);
             }
         }
-        self.liststate.select(None);
-        self.select(0);
+    }
+
+    pub fn notify(&mut self, level: MessageLevel, text: String) {
+        self.message = Some(Message {
+            level,
+            text,
+            expires: Instant::now() + self.message_timeout,
+        });
+    }
+
+    pub fn info(&mut self, text: String) {
+        self.notify(MessageLevel::Info, text);
+    }
+
+    pub fn error(&mut self, text: String) {
+        self.notify(MessageLevel::Error, text);
+    }
+
+    // returns whether the message was removed
+    pub fn expire_message(&mut self) -> bool {
+        if matches!(&self.message, Some(msg) if msg.expires <= Instant::now()) {
+            self.message = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn handle_error(&mut self, e: &Error) {
+        if e.downcast_ref::<MpdError>().is_none() {
+            self.connected = false;
+        }
+        self.error(format!("{e:#}"));
     }
 
     pub fn quit_search(&mut self) {
         self.searching = false;
-        if !self.query.is_empty() {
+        if self.search.editing {
+            self.search.editing = false;
+        } else if !self.query.is_empty() {
             self.query.clear();
             self.reselect();
         }
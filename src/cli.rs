@@ -34,9 +34,19 @@ pub struct Opts {
     pub no_cycle: bool,
 
     /// Specify the address of the mpd server
+    ///
+    /// Absolute paths and paths starting with `~/` are treated as unix sockets
     #[arg(long, value_name = "address")]
     pub address: Option<String>,
 
+    /// Specify the password of the mpd server
+    #[arg(long, value_name = "password")]
+    pub password: Option<String>,
+
+    /// Read the password of the mpd server from a file
+    #[arg(long, value_name = "file", conflicts_with = "password")]
+    pub password_file: Option<PathBuf>,
+
     /// Specify the config file
     #[arg(short, long, value_name = "file")]
     pub config: Option<PathBuf>,
@@ -49,7 +59,11 @@ pub struct Opts {
     #[arg(long, value_name = "number")]
     pub seek_secs: Option<f32>,
 
-    /// The amount of status updates per second
+    /// The percentage to change the volume by
+    #[arg(long, value_name = "number")]
+    pub volume_step: Option<u8>,
+
+    /// The amount of screen updates per second
     #[arg(long, value_name = "number")]
     pub ups: Option<f32>,
 }
@@ -0,0 +1,452 @@
+use std::str::FromStr;
+
+use crate::{
+    app::Command,
+    mpd::{check_command, quote},
+};
+
+/// The commands handled by mmtc, any other command is sent to mpd
+pub const COMMANDS: [&str; 7] = ["load", "q", "quit", "save", "seek", "set", "volume"];
+
+/// The options that can be changed with `:set`
+pub const SETTINGS: [&str; 4] = ["cycle", "jump_lines", "seek_secs", "volume_step"];
+
+pub struct Settings {
+    pub cycle: bool,
+    pub jump_lines: usize,
+    pub seek_secs: f32,
+    pub volume_step: u8,
+}
+
+/// A line typed on the command line
+#[derive(Debug)]
+pub enum Line {
+    Command(Command),
+    Seek(String), // the argument of seekcur
+    SetVolume(u8),
+    ChangeVolume(i16),
+    Save(String),
+    Load(String),
+    Set(Vec<String>),
+    Mpd(String),
+}
+
+/// What the word at the end of the command line can be completed with
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    Command,
+    Playlist,
+    Setting,
+    TagType,
+    TagValue(String), // the tag name
+    Nothing,
+}
+
+/// The command line while it is being typed
+#[derive(Default)]
+pub struct Editor {
+    // the index of the shown history entry and the line typed before browsing the history
+    history: Option<(usize, String)>,
+    completions: Option<Completions>,
+}
+
+struct Completions {
+    start: usize,
+    words: Vec<String>,
+    index: usize,
+    line: String, // the line after the last completion, typing anything else stops cycling
+}
+
+/// A word of a command line and the byte range it spans
+#[derive(Debug, PartialEq)]
+struct Word {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+impl Settings {
+    /// Applies an argument of `:set`, returns the value of the option if it was only queried
+    ///
+    /// `name` enables, `noname` disables and `name!` toggles a boolean option,
+    /// `name=value` sets any option, and `name?` or the name of a number shows the value
+    pub fn set(&mut self, arg: &str) -> Result<Option<String>, String> {
+        fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid value for {name}: {value}"))
+        }
+
+        match arg.split_once('=') {
+            Some(("cycle", value)) => self.cycle = parse("cycle", value)?,
+            Some(("jump_lines", value)) => self.jump_lines = parse("jump_lines", value)?,
+            Some(("seek_secs", value)) => {
+                let secs: f32 = parse("seek_secs", value)?;
+                if !(secs.is_finite() && secs > 0.0) {
+                    return Err(format!("Invalid value for seek_secs: {value}"));
+                }
+                self.seek_secs = secs;
+            }
+            Some(("volume_step", value)) => self.volume_step = parse("volume_step", value)?,
+            Some((name, _)) => return Err(format!("Unknown option {name}")),
+            None => match arg {
+                "cycle" => self.cycle = true,
+                "nocycle" => self.cycle = false,
+                "cycle!" | "invcycle" => self.cycle = !self.cycle,
+                _ => {
+                    let name = arg.strip_suffix('?').unwrap_or(arg);
+                    return self
+                        .get(name)
+                        .map(Some)
+                        .ok_or_else(|| format!("Unknown option {name}"));
+                }
+            },
+        }
+
+        Ok(None)
+    }
+
+    /// The value of an option formatted like `name=value`
+    pub fn get(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "cycle" => format!("cycle={}", self.cycle),
+            "jump_lines" => format!("jump_lines={}", self.jump_lines),
+            "seek_secs" => format!("seek_secs={}", self.seek_secs),
+            "volume_step" => format!("volume_step={}", self.volume_step),
+            _ => return None,
+        })
+    }
+}
+
+/// Parses a non-empty command line, lines starting with an uppercase letter are bindable
+/// [`Command`]s written like in the configuration, e.g. `Next` or `Mpd(["clear"])`
+pub fn parse(line: &str) -> Result<Line, String> {
+    let line = line.trim();
+    if line.starts_with(char::is_uppercase) {
+        let cmd =
+            ron::from_str::<Command>(line).map_err(|e| format!("Invalid command {line}: {e}"))?;
+        return if cmd.text_input().is_some() {
+            Err(format!("{line} can only be bound to keys"))
+        } else {
+            Ok(Line::Command(cmd))
+        };
+    }
+
+    let words = split(line);
+    let Some((name, args)) = words.split_first() else {
+        return Err(String::from("No command was given"));
+    };
+    let arg = || match args {
+        [arg] => Ok(arg.text.clone()),
+        _ => Err(format!("{} takes one argument", name.text)),
+    };
+
+    Ok(match name.text.as_str() {
+        "q" | "quit" => Line::Command(Command::Quit),
+        "seek" => {
+            let arg = arg()?;
+            let (sign, time) = match arg.strip_prefix(['+', '-']) {
+                Some(time) => (&arg[.. 1], time),
+                None => ("", arg.as_str()),
+            };
+            let secs = parse_time(time).ok_or_else(|| format!("Invalid time {time}"))?;
+            Line::Seek(format!("{sign}{secs}"))
+        }
+        "volume" => {
+            let arg = arg()?;
+            if arg.starts_with(['+', '-']) {
+                Line::ChangeVolume(arg.parse().map_err(|_| format!("Invalid volume {arg}"))?)
+            } else {
+                Line::SetVolume(
+                    arg.parse()
+                        .ok()
+                        .filter(|&volume| volume <= 100)
+                        .ok_or_else(|| format!("Invalid volume {arg}"))?,
+                )
+            }
+        }
+        "save" => Line::Save(arg()?),
+        "load" => Line::Load(arg()?),
+        "set" => Line::Set(args.iter().map(|arg| arg.text.clone()).collect()),
+        _ => {
+            check_command(line)?;
+            Line::Mpd(line.into())
+        }
+    })
+}
+
+/// Parses times like `90`, `1:30` or `1:01:30.5` into seconds
+fn parse_time(time: &str) -> Option<f64> {
+    time.split(':').try_fold(0.0, |secs, part| {
+        let x: f64 = part.parse().ok()?;
+        (x.is_finite() && x >= 0.0).then_some(secs * 60.0 + x)
+    })
+}
+
+/// Finds the word at the end of a command line, returns where it starts,
+/// its text without quotes, and what it can be completed with
+pub fn target(line: &str) -> (usize, String, Target) {
+    let mut words = split(line);
+    let word = match words.last() {
+        Some(word) if word.end == line.len() => words.pop(),
+        _ => None,
+    };
+    let (start, word) = word.map_or((line.len(), String::new()), |word| (word.start, word.text));
+
+    // the arguments of filters alternate between tags and values
+    fn filter(args: &[Word]) -> Target {
+        match args.last() {
+            Some(tag) if args.len() % 2 == 1 => Target::TagValue(tag.text.clone()),
+            _ => Target::TagType,
+        }
+    }
+
+    let target = match words.split_first() {
+        None => Target::Command,
+        Some((cmd, args)) => match cmd.text.as_str() {
+            "set" => Target::Setting,
+            "save" | "load" | "rm" | "rename" | "listplaylist" | "listplaylistinfo"
+            | "playlistadd" | "playlistclear" | "playlistdelete" | "playlistmove" | "findaddpl"
+            | "searchaddpl"
+                if args.is_empty() =>
+            {
+                Target::Playlist
+            }
+            "list" if args.is_empty() => Target::TagType,
+            "findaddpl" | "searchaddpl" | "list" => filter(&args[1 ..]),
+            "find" | "search" | "findadd" | "searchadd" | "count" => filter(args),
+            _ => Target::Nothing,
+        },
+    };
+
+    (start, word, target)
+}
+
+/// Splits a command line into words like mpd does,
+/// double quotes group words and backslashes escape characters between them
+fn split(line: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut end = line.len();
+        if c == '"' {
+            chars.next();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    '\\' => text.extend(chars.next().map(|(_, c)| c)),
+                    _ => text.push(c),
+                }
+            }
+        } else {
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_whitespace() {
+                    end = i;
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+        }
+        words.push(Word { start, end, text });
+    }
+
+    words
+}
+
+/// Quotes a word for the command line if mpd would not read it as a single word
+fn arg(word: &str) -> String {
+    if word.is_empty()
+        || word.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '\\'))
+    {
+        quote(word)
+    } else {
+        word.into()
+    }
+}
+
+impl Editor {
+    /// Replaces the line with the previous line of the history
+    pub fn history_up(&mut self, input: &mut String, history: &[String]) {
+        let i = match &self.history {
+            Some((0, _)) => return,
+            Some((i, _)) => i - 1,
+            None if history.is_empty() => return,
+            None => {
+                self.history = Some((history.len(), input.clone()));
+                history.len() - 1
+            }
+        };
+        if let Some((shown, _)) = &mut self.history {
+            *shown = i;
+        }
+        input.clone_from(&history[i]);
+    }
+
+    /// Replaces the line with the next line of the history, or the line typed before browsing it
+    pub fn history_down(&mut self, input: &mut String, history: &[String]) {
+        match self.history.take() {
+            Some((i, typed)) if i + 1 >= history.len() => *input = typed,
+            Some((i, typed)) => {
+                input.clone_from(&history[i + 1]);
+                self.history = Some((i + 1, typed));
+            }
+            None => {}
+        }
+    }
+
+    /// Shows the next or previous completion if the line is the last completion,
+    /// returns whether it did
+    pub fn cycle(&mut self, input: &mut String, forward: bool) -> bool {
+        let Some(completions) = &mut self.completions else {
+            return false;
+        };
+        if completions.line != *input {
+            self.completions = None;
+            return false;
+        }
+
+        let len = completions.words.len();
+        completions.index = if forward {
+            (completions.index + 1) % len
+        } else {
+            (completions.index + len - 1) % len
+        };
+        completions.apply(input);
+        true
+    }
+
+    /// Completes the word starting at `start` with the candidates it is a prefix of,
+    /// ignoring case, the other candidates can be cycled through with [`Editor::cycle`]
+    pub fn complete(
+        &mut self,
+        input: &mut String,
+        start: usize,
+        word: &str,
+        mut candidates: Vec<String>,
+        forward: bool,
+    ) {
+        let word = word.to_lowercase();
+        candidates.retain(|candidate| candidate.to_lowercase().starts_with(&word));
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        match candidates.len() {
+            0 => {}
+            1 => {
+                input.truncate(start);
+                input.push_str(&arg(&candidates[0]));
+                input.push(' ');
+            }
+            len => {
+                let mut completions = Completions {
+                    start,
+                    words: candidates,
+                    index: if forward { 0 } else { len - 1 },
+                    line: String::new(),
+                };
+                completions.apply(input);
+                self.completions = Some(completions);
+            }
+        }
+    }
+}
+
+impl Completions {
+    fn apply(&mut self, input: &mut String) {
+        input.truncate(self.start);
+        input.push_str(&arg(&self.words[self.index]));
+        self.line.clone_from(input);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, split, target, Line, Settings, Target, Word};
+
+    #[test]
+    fn words() {
+        assert_eq!(
+            split(r#" find  artist "a \"b\" c" "#),
+            [
+                Word {
+                    start: 1,
+                    end: 5,
+                    text: "find".into(),
+                },
+                Word {
+                    start: 7,
+                    end: 13,
+                    text: "artist".into(),
+                },
+                Word {
+                    start: 14,
+                    end: 25,
+                    text: r#"a "b" c"#.into(),
+                },
+            ],
+        );
+
+        assert_eq!(target(""), (0, "".into(), Target::Command));
+        assert_eq!(target("pla"), (0, "pla".into(), Target::Command));
+        assert_eq!(target("load "), (5, "".into(), Target::Playlist));
+        assert_eq!(target("load a "), (7, "".into(), Target::Nothing));
+        assert_eq!(target("set c"), (4, "c".into(), Target::Setting));
+        assert_eq!(target("list "), (5, "".into(), Target::TagType));
+        assert_eq!(
+            target("list album art"),
+            (11, "art".into(), Target::TagType)
+        );
+        assert_eq!(
+            target(r#"find artist "the b"#),
+            (12, "the b".into(), Target::TagValue("artist".into())),
+        );
+    }
+
+    #[test]
+    fn lines() {
+        assert!(matches!(parse("seek 1:30"), Ok(Line::Seek(time)) if time == "90"));
+        assert!(matches!(parse("seek -1:00:01.5"), Ok(Line::Seek(time)) if time == "-3601.5"));
+        assert!(parse("seek 1:x").is_err());
+        assert!(matches!(parse("volume 40"), Ok(Line::SetVolume(40))));
+        assert!(matches!(parse("volume -5"), Ok(Line::ChangeVolume(-5))));
+        assert!(parse("volume 101").is_err());
+        assert!(matches!(parse(r#"save "my list""#), Ok(Line::Save(name)) if name == "my list"));
+        assert!(parse("save my list").is_err());
+        assert!(matches!(parse("Next"), Ok(Line::Command(_))));
+        assert!(parse("SearchLibrary").is_err());
+        assert!(matches!(parse(" status "), Ok(Line::Mpd(line)) if line == "status"));
+        assert!(parse("idle player").is_err());
+        assert!(parse("close").is_err());
+        assert!(parse("command_list_ok_begin").is_err());
+        assert!(parse("partition other").is_err());
+
+        let mut settings = Settings {
+            cycle: false,
+            jump_lines: 10,
+            seek_secs: 5.0,
+            volume_step: 5,
+        };
+        assert_eq!(settings.set("cycle"), Ok(None));
+        assert!(settings.cycle);
+        assert_eq!(settings.set("cycle!"), Ok(None));
+        assert!(!settings.cycle);
+        assert_eq!(settings.set("jump_lines=3"), Ok(None));
+        assert_eq!(settings.set("jump_lines"), Ok(Some("jump_lines=3".into())));
+        assert!(settings.set("volume_step=x").is_err());
+        assert!(settings.set("seek_secs=-5").is_err());
+        assert!(settings.set("seek_secs=NaN").is_err());
+        assert_eq!(settings.set("seek_secs=2.5"), Ok(None));
+        assert!(settings.set("colour").is_err());
+    }
+}
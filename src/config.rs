@@ -1,18 +1,29 @@
-use std::fmt::{self, Formatter};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+};
 
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use ratatui::style::Color;
 use serde::{
-    de::{self, EnumAccess, SeqAccess, VariantAccess, Visitor},
+    de::{self, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor},
     Deserialize, Deserializer,
 };
 
-use crate::defaults;
+use crate::{app::Command, defaults};
 
 #[derive(Deserialize)]
 pub struct Config {
     #[serde(default = "defaults::address")]
     pub address: String,
     #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub password_file: Option<PathBuf>,
+    #[serde(default)]
     pub clear_query_on_play: bool,
     #[serde(default)]
     pub cycle: bool,
@@ -20,10 +31,22 @@ pub struct Config {
     pub jump_lines: usize,
     #[serde(default = "defaults::seek_secs")]
     pub seek_secs: f32,
+    #[serde(default = "defaults::volume_step")]
+    pub volume_step: u8,
     #[serde(default = "defaults::search_fields")]
     pub search_fields: SearchFields,
     #[serde(default = "defaults::ups")]
     pub ups: f32,
+    #[serde(default = "defaults::message_timeout")]
+    pub message_timeout: f32,
+    #[serde(default = "defaults::time_format")]
+    pub time_format: TimeFormats,
+    #[serde(default = "defaults::library")]
+    pub library: Vec<Vec<String>>,
+    #[serde(default = "defaults::bindings")]
+    pub bindings: Bindings,
+    #[serde(default = "defaults::key_timeout")]
+    pub key_timeout: f32,
     #[serde(default = "defaults::layout")]
     pub layout: Widget,
 }
@@ -40,10 +63,106 @@ pub struct SearchFields {
     pub album: bool,
 }
 
+#[derive(Deserialize)]
+pub struct TimeFormats {
+    #[serde(default = "defaults::short_time_format")]
+    pub short: TimeFormat,
+    #[serde(default = "defaults::long_time_format")]
+    pub long: TimeFormat,
+}
+
+pub struct TimeFormat(pub Vec<TimePart>);
+
+/// The commands bound to key sequences, one map for each input mode
+#[derive(Default, Deserialize)]
+pub struct Bindings {
+    #[serde(default = "defaults::normal_bindings", deserialize_with = "bindings")]
+    pub normal: HashMap<Vec<Chord>, Command>,
+    #[serde(default = "defaults::search_bindings", deserialize_with = "bindings")]
+    pub search: HashMap<Vec<Chord>, Command>,
+}
+
+/// A key or a mouse button pressed with modifiers
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Chord {
+    Key(KeyCode, KeyModifiers),
+    Mouse(MouseEventKind, KeyModifiers),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TimePart {
+    Text(String),
+    Hours,
+    Minutes,
+    MinutesPadded,
+    Seconds,
+    SecondsPadded,
+    Tenths,
+}
+
 fn yes() -> bool {
     true
 }
 
+fn some<'de, D: Deserializer<'de>, T: Deserialize<'de>>(de: D) -> Result<Option<T>, D::Error> {
+    T::deserialize(de).map(Some)
+}
+
+fn bindings<'de, D: Deserializer<'de>>(de: D) -> Result<HashMap<Vec<Chord>, Command>, D::Error> {
+    struct BindingsVisitor;
+    impl<'de> Visitor<'de> for BindingsVisitor {
+        type Value = HashMap<Vec<Chord>, Command>;
+
+        fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+            formatter.write_str("a map from keys to commands")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut ma: A) -> Result<Self::Value, A::Error> {
+            let mut bindings = HashMap::new();
+            while let Some((keys, cmd)) = ma.next_entry::<String, _>()? {
+                let keys = Chord::parse_keys(&keys).map_err(de::Error::custom)?;
+                // a sequence can't be completed if a prefix of it is also bound
+                if let Some(other) = bindings
+                    .keys()
+                    .find(|other: &&Vec<_>| keys.starts_with(other) || other.starts_with(&keys))
+                {
+                    return Err(de::Error::custom(if *other == keys {
+                        format!("conflicting bindings for {}", keys_string(&keys))
+                    } else {
+                        format!(
+                            "conflicting bindings for {} and {}",
+                            keys_string(other),
+                            keys_string(&keys),
+                        )
+                    }));
+                }
+                bindings.insert(keys, cmd);
+            }
+            Ok(bindings)
+        }
+    }
+
+    de.deserialize_map(BindingsVisitor)
+}
+
+/// Key sequences are written as chords separated by spaces
+pub fn keys_string(keys: &[Chord]) -> String {
+    keys.iter()
+        .map(Chord::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Clone, Copy, Deserialize, Eq, PartialEq)]
+pub enum View {
+    Queue,
+    Browser,
+    Library,
+    Search,
+    Playlists,
+    Outputs,
+}
+
 #[derive(Deserialize)]
 pub enum Widget {
     Rows(Vec<Constrained<Widget>>),
@@ -53,6 +172,28 @@ pub enum Widget {
     TextboxC(Texts),
     TextboxR(Texts),
     Queue(Vec<Column>),
+    Browser(Vec<Column>),
+    Library(Vec<Column>),
+    Search(Vec<Column>),
+    Playlists(Vec<Column>),
+    Outputs(Vec<Column>),
+    If(
+        Condition,
+        Box<Widget>,
+        #[serde(default, deserialize_with = "some")] Option<Box<Widget>>,
+    ),
+    Progress {
+        #[serde(default = "defaults::progress_filled")]
+        filled: String,
+        #[serde(default = "defaults::progress_empty")]
+        empty: String,
+        #[serde(default)]
+        style: Vec<AddStyle>,
+        #[serde(default)]
+        empty_style: Vec<AddStyle>,
+        #[serde(default)]
+        label: Option<Texts>,
+    },
 }
 
 #[derive(Deserialize)]
@@ -71,12 +212,27 @@ pub enum Texts {
     CurrentTitle,
     CurrentArtist,
     CurrentAlbum,
+    CurrentTag(String),
     QueueDuration,
     QueueFile,
     QueueTitle,
     QueueArtist,
     QueueAlbum,
+    QueueTag(String),
+    QueuePriority,
+    EntryName,
+    BrowserPath,
+    LibraryTag(String),
     Query,
+    SearchQuery,
+    PlaylistName,
+    Prompt,
+    PromptInput,
+    OutputName,
+    OutputPlugin,
+    PendingKeys,
+    Message,
+    Volume,
     Styled(Vec<AddStyle>, Box<Texts>),
     Parts(Vec<Texts>),
     If(Condition, Box<Texts>, Option<Box<Texts>>),
@@ -113,17 +269,33 @@ pub enum Condition {
     Single,
     Oneshot,
     Consume,
+    Muted,
     Playing,
     Paused,
     Stopped,
     TitleExist,
     ArtistExist,
     AlbumExist,
+    CurrentTagExist(String),
     QueueTitleExist,
+    QueueTagExist(String),
     QueueCurrent,
+    QueueNext,
     Selected,
+    Marked,
+    Visual,
+    EntryDirectory,
+    EntrySong,
+    EntryPlaylist,
+    OutputEnabled,
+    Focused(View),
     Searching,
+    SearchingLibrary,
+    Prompting,
     Filtered,
+    Connected,
+    HasMessage,
+    HasError,
     Not(Box<Condition>),
     And(Box<Condition>, Box<Condition>),
     Or(Box<Condition>, Box<Condition>),
@@ -163,12 +335,27 @@ impl<'de> Deserialize<'de> for Texts {
                     CurrentTitle,
                     CurrentArtist,
                     CurrentAlbum,
+                    CurrentTag,
                     QueueDuration,
                     QueueFile,
                     QueueTitle,
                     QueueArtist,
                     QueueAlbum,
+                    QueueTag,
+                    QueuePriority,
+                    EntryName,
+                    BrowserPath,
+                    LibraryTag,
                     Query,
+                    SearchQuery,
+                    PlaylistName,
+                    Prompt,
+                    PromptInput,
+                    OutputName,
+                    OutputPlugin,
+                    PendingKeys,
+                    Message,
+                    Volume,
                     Styled,
                     Parts,
                     If,
@@ -236,12 +423,27 @@ impl<'de> Deserialize<'de> for Texts {
                     Variant::CurrentTitle => unit_variant!(CurrentTitle),
                     Variant::CurrentArtist => unit_variant!(CurrentArtist),
                     Variant::CurrentAlbum => unit_variant!(CurrentAlbum),
+                    Variant::CurrentTag => Ok(Texts::CurrentTag(va.newtype_variant()?)),
                     Variant::QueueDuration => unit_variant!(QueueDuration),
                     Variant::QueueFile => unit_variant!(QueueFile),
                     Variant::QueueTitle => unit_variant!(QueueTitle),
                     Variant::QueueArtist => unit_variant!(QueueArtist),
                     Variant::QueueAlbum => unit_variant!(QueueAlbum),
+                    Variant::QueueTag => Ok(Texts::QueueTag(va.newtype_variant()?)),
+                    Variant::QueuePriority => unit_variant!(QueuePriority),
+                    Variant::EntryName => unit_variant!(EntryName),
+                    Variant::BrowserPath => unit_variant!(BrowserPath),
+                    Variant::LibraryTag => Ok(Texts::LibraryTag(va.newtype_variant()?)),
                     Variant::Query => unit_variant!(Query),
+                    Variant::SearchQuery => unit_variant!(SearchQuery),
+                    Variant::PlaylistName => unit_variant!(PlaylistName),
+                    Variant::Prompt => unit_variant!(Prompt),
+                    Variant::PromptInput => unit_variant!(PromptInput),
+                    Variant::OutputName => unit_variant!(OutputName),
+                    Variant::OutputPlugin => unit_variant!(OutputPlugin),
+                    Variant::PendingKeys => unit_variant!(PendingKeys),
+                    Variant::Message => unit_variant!(Message),
+                    Variant::Volume => unit_variant!(Volume),
                     Variant::Styled => va.tuple_variant(2, StyledVisitor),
                     Variant::Parts => Ok(Texts::Parts(va.newtype_variant()?)),
                     Variant::If => va.tuple_variant(3, IfVisitor),
@@ -259,12 +461,27 @@ impl<'de> Deserialize<'de> for Texts {
                 "CurrentTitle",
                 "CurrentArtist",
                 "CurrentAlbum",
+                "CurrentTag",
                 "QueueDuration",
                 "QueueFile",
                 "QueueTitle",
                 "QueueArtist",
                 "QueueAlbum",
+                "QueueTag",
+                "QueuePriority",
+                "EntryName",
+                "BrowserPath",
+                "LibraryTag",
                 "Query",
+                "SearchQuery",
+                "PlaylistName",
+                "Prompt",
+                "PromptInput",
+                "OutputName",
+                "OutputPlugin",
+                "PendingKeys",
+                "Message",
+                "Volume",
                 "Styled",
                 "Parts",
                 "If",
@@ -273,3 +490,286 @@ impl<'de> Deserialize<'de> for Texts {
         )
     }
 }
+
+impl<'de> Deserialize<'de> for TimeFormat {
+    fn deserialize<D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TimeFormatVisitor;
+        impl<'de> Visitor<'de> for TimeFormatVisitor {
+            type Value = TimeFormat;
+
+            fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                formatter.write_str("a time format string")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                TimeFormat::parse(v).map_err(E::custom)
+            }
+        }
+
+        de.deserialize_str(TimeFormatVisitor)
+    }
+}
+
+impl TimeFormat {
+    fn parse(fmt: &str) -> Result<TimeFormat, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = fmt.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('{') if name.is_empty() => {
+                                text.push('{');
+                                break;
+                            }
+                            Some('}') => {
+                                if !text.is_empty() {
+                                    parts.push(TimePart::Text(text.split_off(0)));
+                                }
+                                parts.push(match &*name {
+                                    "h" => TimePart::Hours,
+                                    "m" => TimePart::Minutes,
+                                    "mm" => TimePart::MinutesPadded,
+                                    "s" => TimePart::Seconds,
+                                    "ss" => TimePart::SecondsPadded,
+                                    "f" => TimePart::Tenths,
+                                    _ => return Err(format!("unknown placeholder {{{name}}}")),
+                                });
+                                break;
+                            }
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed placeholder in {fmt:?}")),
+                        }
+                    }
+                }
+                '}' => {
+                    if chars.next() != Some('}') {
+                        return Err(format!("unmatched }} in {fmt:?}, use }}}} to escape it"));
+                    }
+                    text.push('}');
+                }
+                _ => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(TimePart::Text(text));
+        }
+
+        Ok(TimeFormat(parts))
+    }
+}
+
+impl Chord {
+    /// Parses chords separated by spaces, e.g. `g g`
+    pub fn parse_keys(keys: &str) -> Result<Vec<Chord>, String> {
+        let keys = keys
+            .split_whitespace()
+            .map(Chord::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            Err(String::from(
+                "empty key sequence, use \"Space\" for the space key",
+            ))
+        } else {
+            Ok(keys)
+        }
+    }
+
+    /// Parses modifiers followed by a key, e.g. `Ctrl-Alt-x`, `Shift-Left` or `ScrollDown`
+    pub fn parse(chord: &str) -> Result<Chord, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = chord;
+        'modifiers: loop {
+            for (prefix, modifier) in [
+                ("Ctrl-", KeyModifiers::CONTROL),
+                ("Alt-", KeyModifiers::ALT),
+                ("Shift-", KeyModifiers::SHIFT),
+            ] {
+                if let Some(key) = rest.strip_prefix(prefix).filter(|key| !key.is_empty()) {
+                    modifiers |= modifier;
+                    rest = key;
+                    continue 'modifiers;
+                }
+            }
+            break;
+        }
+
+        let mut chars = rest.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(Chord::key(KeyCode::Char(c), modifiers));
+        }
+
+        let mouse = |kind| Ok(Chord::Mouse(kind, modifiers));
+        let code = match rest {
+            "Space" => KeyCode::Char(' '),
+            "Enter" => KeyCode::Enter,
+            "Esc" => KeyCode::Esc,
+            "Backspace" => KeyCode::Backspace,
+            "Delete" => KeyCode::Delete,
+            "Insert" => KeyCode::Insert,
+            "Tab" => KeyCode::Tab,
+            "BackTab" => KeyCode::BackTab,
+            "Left" => KeyCode::Left,
+            "Right" => KeyCode::Right,
+            "Up" => KeyCode::Up,
+            "Down" => KeyCode::Down,
+            "Home" => KeyCode::Home,
+            "End" => KeyCode::End,
+            "PageUp" => KeyCode::PageUp,
+            "PageDown" => KeyCode::PageDown,
+            "LeftClick" => return mouse(MouseEventKind::Down(MouseButton::Left)),
+            "RightClick" => return mouse(MouseEventKind::Down(MouseButton::Right)),
+            "MiddleClick" => return mouse(MouseEventKind::Down(MouseButton::Middle)),
+            "LeftDrag" => return mouse(MouseEventKind::Drag(MouseButton::Left)),
+            "RightDrag" => return mouse(MouseEventKind::Drag(MouseButton::Right)),
+            "MiddleDrag" => return mouse(MouseEventKind::Drag(MouseButton::Middle)),
+            "ScrollUp" => return mouse(MouseEventKind::ScrollUp),
+            "ScrollDown" => return mouse(MouseEventKind::ScrollDown),
+            "ScrollLeft" => return mouse(MouseEventKind::ScrollLeft),
+            "ScrollRight" => return mouse(MouseEventKind::ScrollRight),
+            _ => match rest.strip_prefix('F').and_then(|n| n.parse().ok()) {
+                Some(n @ 1 ..= 24) => KeyCode::F(n),
+                _ => return Err(format!("unknown key {rest:?} in {chord:?}")),
+            },
+        };
+
+        Ok(Chord::key(code, modifiers))
+    }
+
+    /// The chord of a terminal event, None if the event is not a key press or a mouse button
+    pub fn from_event(ev: &Event) -> Option<Chord> {
+        match *ev {
+            Event::Key(KeyEvent {
+                code, modifiers, ..
+            }) => Some(Chord::key(code, modifiers)),
+            Event::Mouse(MouseEvent {
+                kind:
+                    kind @ (MouseEventKind::Down(_)
+                    | MouseEventKind::Drag(_)
+                    | MouseEventKind::ScrollUp
+                    | MouseEventKind::ScrollDown
+                    | MouseEventKind::ScrollLeft
+                    | MouseEventKind::ScrollRight),
+                modifiers,
+                ..
+            }) => Some(Chord::Mouse(kind, modifiers)),
+            _ => None,
+        }
+    }
+
+    /// Shift is part of the character typed, and of BackTab
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Chord {
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => Chord::Key(
+                KeyCode::Char(c.to_uppercase().next().unwrap_or(c)),
+                modifiers - KeyModifiers::SHIFT,
+            ),
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => {
+                Chord::Key(KeyCode::BackTab, modifiers - KeyModifiers::SHIFT)
+            }
+            KeyCode::BackTab => Chord::Key(code, modifiers - KeyModifiers::SHIFT),
+            _ => Chord::Key(code, modifiers),
+        }
+    }
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (Chord::Key(_, modifiers) | Chord::Mouse(_, modifiers)) = *self;
+        for (prefix, modifier) in [
+            ("Ctrl-", KeyModifiers::CONTROL),
+            ("Alt-", KeyModifiers::ALT),
+            ("Shift-", KeyModifiers::SHIFT),
+        ] {
+            if modifiers.contains(modifier) {
+                f.write_str(prefix)?;
+            }
+        }
+
+        match *self {
+            Chord::Key(KeyCode::Char(' '), _) => f.write_str("Space"),
+            Chord::Key(KeyCode::Char(c), _) => write!(f, "{c}"),
+            Chord::Key(KeyCode::F(n), _) => write!(f, "F{n}"),
+            Chord::Key(code, _) => write!(f, "{code:?}"),
+            Chord::Mouse(kind, _) => match kind {
+                MouseEventKind::Down(button) => write!(f, "{button:?}Click"),
+                MouseEventKind::Drag(button) => write!(f, "{button:?}Drag"),
+                _ => write!(f, "{kind:?}"),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyModifiers, MouseButton, MouseEventKind};
+
+    use super::{Chord, TimeFormat, TimePart};
+
+    #[test]
+    fn chords() {
+        for (chord, expected) in [
+            ("q", Chord::Key(KeyCode::Char('q'), KeyModifiers::NONE)),
+            (
+                "Shift-q",
+                Chord::Key(KeyCode::Char('Q'), KeyModifiers::NONE),
+            ),
+            (
+                "Ctrl--",
+                Chord::Key(KeyCode::Char('-'), KeyModifiers::CONTROL),
+            ),
+            (
+                "Shift-Tab",
+                Chord::Key(KeyCode::BackTab, KeyModifiers::NONE),
+            ),
+            (
+                "Ctrl-Alt-F5",
+                Chord::Key(KeyCode::F(5), KeyModifiers::CONTROL | KeyModifiers::ALT),
+            ),
+            (
+                "Shift-LeftClick",
+                Chord::Mouse(MouseEventKind::Down(MouseButton::Left), KeyModifiers::SHIFT),
+            ),
+        ] {
+            assert_eq!(Chord::parse(chord), Ok(expected));
+            assert_eq!(Chord::parse(&expected.to_string()), Ok(expected));
+        }
+        assert!(Chord::parse("Ctrl-").is_err());
+        assert!(Chord::parse("F25").is_err());
+        assert_eq!(
+            Chord::parse_keys("g  Ctrl-g"),
+            Ok(vec![
+                Chord::Key(KeyCode::Char('g'), KeyModifiers::NONE),
+                Chord::Key(KeyCode::Char('g'), KeyModifiers::CONTROL),
+            ]),
+        );
+        assert!(Chord::parse_keys(" ").is_err());
+    }
+
+    #[test]
+    fn time_formats() {
+        let TimeFormat(parts) = TimeFormat::parse("{{{m}}}:{ss}.{f}").unwrap();
+        assert_eq!(
+            parts,
+            [
+                TimePart::Text("{".into()),
+                TimePart::Minutes,
+                TimePart::Text("}:".into()),
+                TimePart::SecondsPadded,
+                TimePart::Text(".".into()),
+                TimePart::Tenths,
+            ],
+        );
+        assert!(TimeFormat::parse("{m}:{ss").is_err());
+        assert!(TimeFormat::parse("{m}}").is_err());
+        assert!(TimeFormat::parse("{x}").is_err());
+    }
+}
@@ -1,18 +1,33 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyModifiers, MouseButton, MouseEventKind};
 use ratatui::style::Color;
 
-use crate::config::{
-    AddStyle, Column, Condition, Config, Constrained, SearchFields, Texts, Widget,
+use crate::{
+    app::Command,
+    config::{
+        AddStyle, Bindings, Chord, Column, Condition, Config, Constrained, SearchFields, Texts,
+        TimeFormat, TimeFormats, TimePart, Widget,
+    },
 };
 
 pub fn config() -> Config {
     Config {
         address: address(),
+        password: None,
+        password_file: None,
         clear_query_on_play: false,
         cycle: false,
         jump_lines: jump_lines(),
         seek_secs: seek_secs(),
+        volume_step: volume_step(),
         search_fields: search_fields(),
         ups: ups(),
+        message_timeout: message_timeout(),
+        time_format: time_format(),
+        library: library(),
+        bindings: bindings(),
+        key_timeout: key_timeout(),
         layout: layout(),
     }
 }
@@ -29,6 +44,10 @@ pub fn seek_secs() -> f32 {
     5.0
 }
 
+pub fn volume_step() -> u8 {
+    5
+}
+
 pub fn search_fields() -> SearchFields {
     SearchFields {
         file: false,
@@ -42,6 +61,180 @@ pub fn ups() -> f32 {
     1.0
 }
 
+pub fn message_timeout() -> f32 {
+    5.0
+}
+
+pub fn time_format() -> TimeFormats {
+    TimeFormats {
+        short: short_time_format(),
+        long: long_time_format(),
+    }
+}
+
+pub fn short_time_format() -> TimeFormat {
+    TimeFormat(vec![
+        TimePart::Minutes,
+        TimePart::Text(String::from(":")),
+        TimePart::SecondsPadded,
+    ])
+}
+
+pub fn long_time_format() -> TimeFormat {
+    TimeFormat(vec![
+        TimePart::Hours,
+        TimePart::Text(String::from(":")),
+        TimePart::MinutesPadded,
+        TimePart::Text(String::from(":")),
+        TimePart::SecondsPadded,
+    ])
+}
+
+pub fn progress_filled() -> String {
+    String::from("━")
+}
+
+pub fn progress_empty() -> String {
+    String::from("─")
+}
+
+pub fn library() -> Vec<Vec<String>> {
+    vec![
+        vec![String::from("AlbumArtist")],
+        vec![String::from("Date"), String::from("Album")],
+    ]
+}
+
+pub fn bindings() -> Bindings {
+    Bindings {
+        normal: normal_bindings(),
+        search: search_bindings(),
+    }
+}
+
+pub fn normal_bindings() -> HashMap<Vec<Chord>, Command> {
+    let mut bindings = keys([
+        (ctrl('q'), Command::Quit),
+        (ctrl('u'), Command::JumpUp),
+        (ctrl('d'), Command::JumpDown),
+        (key(KeyCode::Delete), Command::Delete),
+        (key(KeyCode::Enter), Command::Play),
+        (key(KeyCode::Backspace), Command::Back),
+        (key(KeyCode::Esc), Command::QuitSearch),
+        (ch('q'), Command::Quit),
+        (ch('r'), Command::ToggleRepeat),
+        (ch('R'), Command::ToggleRandom),
+        (ch('s'), Command::ToggleSingle),
+        (ch('S'), Command::ToggleOneshot),
+        (ch('c'), Command::ToggleConsume),
+        (ch('p'), Command::TogglePause),
+        (ch(';'), Command::Stop),
+        (ch('h'), Command::SeekBackwards),
+        (ch('l'), Command::SeekForwards),
+        (ch('+'), Command::VolumeUp),
+        (ch('='), Command::VolumeUp),
+        (ch('-'), Command::VolumeDown),
+        (ch('m'), Command::ToggleMute),
+        (ch('['), Command::MoveUp),
+        (ch(']'), Command::MoveDown),
+        (ch('C'), Command::Clear),
+        (ch('Z'), Command::Shuffle),
+        (ch('X'), Command::Crop),
+        (ch('a'), Command::Add),
+        (ch('i'), Command::Insert),
+        (ch('o'), Command::Replace),
+        (ch('A'), Command::AddAll),
+        (ch('O'), Command::ReplaceAll),
+        (ch('>'), Command::PriorityUp),
+        (ch('<'), Command::PriorityDown),
+        (ch('t'), Command::ToggleMark),
+        (ch('v'), Command::Visual),
+        (ch('T'), Command::ClearMarks),
+        (ch('H'), Command::Previous),
+        (ch('L'), Command::Next),
+        (ch(' '), Command::Reselect),
+        (ch('j'), Command::Down),
+        (ch('k'), Command::Up),
+        (ch('J'), Command::JumpDown),
+        (ch('K'), Command::JumpUp),
+        (ch('G'), Command::GotoBottom),
+        (ch('/'), Command::Searching(true)),
+        (ch('?'), Command::SearchLibrary),
+        (ch('w'), Command::SaveQueue),
+        (ch('W'), Command::AddToPlaylist),
+        (ch('n'), Command::RenamePlaylist),
+        (ch(':'), Command::Cmdline),
+        (ch('e'), Command::ToggleOutput),
+        (ch('E'), Command::SwitchOutput),
+    ]);
+    bindings.insert(vec![ch('d'), ch('d')], Command::Delete);
+    bindings.insert(vec![ch('g'), ch('g')], Command::GotoTop);
+    bindings
+}
+
+pub fn search_bindings() -> HashMap<Vec<Chord>, Command> {
+    keys([
+        (ctrl('q'), Command::Quit),
+        (ctrl('u'), Command::ClearSearch),
+        (key(KeyCode::Enter), Command::Searching(false)),
+        (key(KeyCode::Backspace), Command::BackspaceSearch),
+        (key(KeyCode::Esc), Command::QuitSearch),
+    ])
+}
+
+pub fn key_timeout() -> f32 {
+    1.0
+}
+
+// bindings of single keys, including the ones that work in both normal and search mode
+fn keys<const N: usize>(bindings: [(Chord, Command); N]) -> HashMap<Vec<Chord>, Command> {
+    bindings
+        .into_iter()
+        .chain(common_bindings())
+        .map(|(chord, cmd)| (vec![chord], cmd))
+        .collect()
+}
+
+// bindings that work in both normal and search mode
+fn common_bindings() -> [(Chord, Command); 12] {
+    [
+        (key(KeyCode::Tab), Command::FocusNext),
+        (key(KeyCode::BackTab), Command::FocusPrevious),
+        (key(KeyCode::Left), Command::SeekBackwards),
+        (key(KeyCode::Right), Command::SeekForwards),
+        (key(KeyCode::Down), Command::Down),
+        (key(KeyCode::Up), Command::Up),
+        (key(KeyCode::PageDown), Command::JumpDown),
+        (key(KeyCode::PageUp), Command::JumpUp),
+        (mouse(MouseEventKind::ScrollDown), Command::Down),
+        (mouse(MouseEventKind::ScrollUp), Command::Up),
+        (
+            mouse(MouseEventKind::Down(MouseButton::Left)),
+            Command::Click,
+        ),
+        (
+            mouse(MouseEventKind::Drag(MouseButton::Left)),
+            Command::Click,
+        ),
+    ]
+}
+
+fn key(code: KeyCode) -> Chord {
+    Chord::Key(code, KeyModifiers::NONE)
+}
+
+fn ch(c: char) -> Chord {
+    key(KeyCode::Char(c))
+}
+
+fn ctrl(c: char) -> Chord {
+    Chord::Key(KeyCode::Char(c), KeyModifiers::CONTROL)
+}
+
+fn mouse(kind: MouseEventKind) -> Chord {
+    Chord::Mouse(kind, KeyModifiers::NONE)
+}
+
 pub fn layout() -> Widget {
     Widget::Rows(vec![
         Constrained::Fixed(
@@ -83,22 +276,32 @@ pub fn layout() -> Widget {
                 Column {
                     item: Constrained::Ratio(
                         12,
-                        Texts::If(
-                            Condition::QueueCurrent,
-                            Box::new(Texts::Styled(
-                                vec![AddStyle::Italic],
-                                Box::new(Texts::If(
+                        Texts::Parts(vec![
+                            Texts::If(
+                                Condition::Marked,
+                                Box::new(Texts::Styled(
+                                    vec![AddStyle::Fg(Color::Indexed(220)), AddStyle::Bold],
+                                    Box::new(Texts::Text(String::from("+ "))),
+                                )),
+                                None,
+                            ),
+                            Texts::If(
+                                Condition::QueueCurrent,
+                                Box::new(Texts::Styled(
+                                    vec![AddStyle::Italic],
+                                    Box::new(Texts::If(
+                                        Condition::QueueTitleExist,
+                                        Box::new(Texts::QueueTitle),
+                                        Some(Box::new(Texts::QueueFile)),
+                                    )),
+                                )),
+                                Some(Box::new(Texts::If(
                                     Condition::QueueTitleExist,
                                     Box::new(Texts::QueueTitle),
                                     Some(Box::new(Texts::QueueFile)),
-                                )),
-                            )),
-                            Some(Box::new(Texts::If(
-                                Condition::QueueTitleExist,
-                                Box::new(Texts::QueueTitle),
-                                Some(Box::new(Texts::QueueFile)),
-                            ))),
-                        ),
+                                ))),
+                            ),
+                        ]),
                     ),
                     style: vec![AddStyle::Fg(Color::Indexed(75))],
                     selected_style: vec![
@@ -174,15 +377,38 @@ pub fn layout() -> Widget {
                     Widget::Textbox(Texts::Styled(
                         vec![AddStyle::Bold],
                         Box::new(Texts::If(
-                            Condition::Searching,
+                            Condition::Or(
+                                Box::new(Condition::Prompting),
+                                Box::new(Condition::Searching),
+                            ),
                             Box::new(Texts::Parts(vec![
                                 Texts::Styled(
                                     vec![AddStyle::Fg(Color::Indexed(113))],
-                                    Box::new(Texts::Text(String::from("Searching: "))),
+                                    Box::new(Texts::If(
+                                        Condition::Prompting,
+                                        Box::new(Texts::Prompt),
+                                        Some(Box::new(Texts::If(
+                                            Condition::SearchingLibrary,
+                                            Box::new(Texts::Text(String::from(
+                                                "Searching library: ",
+                                            ))),
+                                            Some(Box::new(Texts::Text(String::from(
+                                                "Searching: ",
+                                            )))),
+                                        ))),
+                                    )),
                                 ),
                                 Texts::Styled(
                                     vec![AddStyle::Fg(Color::Indexed(185))],
-                                    Box::new(Texts::Query),
+                                    Box::new(Texts::If(
+                                        Condition::Prompting,
+                                        Box::new(Texts::PromptInput),
+                                        Some(Box::new(Texts::If(
+                                            Condition::SearchingLibrary,
+                                            Box::new(Texts::SearchQuery),
+                                            Some(Box::new(Texts::Query)),
+                                        ))),
+                                    )),
                                 ),
                                 Texts::Styled(
                                     vec![AddStyle::Fg(Color::Indexed(185))],
@@ -190,73 +416,101 @@ pub fn layout() -> Widget {
                                 ),
                             ])),
                             Some(Box::new(Texts::If(
-                                Condition::Not(Box::new(Condition::Stopped)),
-                                Box::new(Texts::Parts(vec![
-                                    Texts::Styled(
-                                        vec![AddStyle::Fg(Color::Indexed(113))],
-                                        Box::new(Texts::Parts(vec![
-                                            Texts::If(
-                                                Condition::Playing,
-                                                Box::new(Texts::Text(String::from("[playing: "))),
-                                                Some(Box::new(Texts::Text(String::from(
-                                                    "[paused:  ",
-                                                )))),
-                                            ),
-                                            Texts::CurrentElapsed,
-                                            Texts::Text(String::from("/")),
-                                            Texts::CurrentDuration,
-                                            Texts::Text(String::from("] ")),
-                                        ])),
-                                    ),
-                                    Texts::If(
-                                        Condition::TitleExist,
-                                        Box::new(Texts::Parts(vec![
-                                            Texts::Styled(
-                                                vec![AddStyle::Fg(Color::Indexed(149))],
-                                                Box::new(Texts::CurrentTitle),
-                                            ),
-                                            Texts::If(
-                                                Condition::ArtistExist,
-                                                Box::new(Texts::Parts(vec![
-                                                    Texts::Styled(
-                                                        vec![AddStyle::Fg(Color::Indexed(216))],
-                                                        Box::new(Texts::Text(String::from(" ◆ "))),
-                                                    ),
-                                                    Texts::Styled(
-                                                        vec![AddStyle::Fg(Color::Indexed(185))],
-                                                        Box::new(Texts::CurrentArtist),
-                                                    ),
-                                                    Texts::If(
-                                                        Condition::AlbumExist,
-                                                        Box::new(Texts::Parts(vec![
-                                                            Texts::Styled(
-                                                                vec![AddStyle::Fg(Color::Indexed(
-                                                                    216,
-                                                                ))],
-                                                                Box::new(Texts::Text(
-                                                                    String::from(" ◆ "),
-                                                                )),
-                                                            ),
-                                                            Texts::Styled(
-                                                                vec![AddStyle::Fg(Color::Indexed(
-                                                                    221,
-                                                                ))],
-                                                                Box::new(Texts::CurrentAlbum),
-                                                            ),
-                                                        ])),
-                                                        None,
-                                                    ),
-                                                ])),
-                                                None,
-                                            ),
-                                        ])),
+                                Condition::Or(
+                                    Box::new(Condition::HasMessage),
+                                    Box::new(Condition::Not(Box::new(Condition::Connected))),
+                                ),
+                                Box::new(Texts::If(
+                                    Condition::HasMessage,
+                                    Box::new(Texts::If(
+                                        Condition::HasError,
+                                        Box::new(Texts::Styled(
+                                            vec![AddStyle::Fg(Color::Indexed(203))],
+                                            Box::new(Texts::Message),
+                                        )),
                                         Some(Box::new(Texts::Styled(
-                                            vec![AddStyle::Fg(Color::Indexed(185))],
-                                            Box::new(Texts::CurrentFile),
+                                            vec![AddStyle::Fg(Color::Indexed(113))],
+                                            Box::new(Texts::Message),
                                         ))),
-                                    ),
-                                ])),
-                                None,
+                                    )),
+                                    Some(Box::new(Texts::Styled(
+                                        vec![AddStyle::Fg(Color::Indexed(203))],
+                                        Box::new(Texts::Text(String::from("[disconnected]"))),
+                                    ))),
+                                )),
+                                Some(Box::new(Texts::If(
+                                    Condition::Not(Box::new(Condition::Stopped)),
+                                    Box::new(Texts::Parts(vec![
+                                        Texts::Styled(
+                                            vec![AddStyle::Fg(Color::Indexed(113))],
+                                            Box::new(Texts::Parts(vec![
+                                                Texts::If(
+                                                    Condition::Playing,
+                                                    Box::new(Texts::Text(String::from(
+                                                        "[playing: ",
+                                                    ))),
+                                                    Some(Box::new(Texts::Text(String::from(
+                                                        "[paused:  ",
+                                                    )))),
+                                                ),
+                                                Texts::CurrentElapsed,
+                                                Texts::Text(String::from("/")),
+                                                Texts::CurrentDuration,
+                                                Texts::Text(String::from("] ")),
+                                            ])),
+                                        ),
+                                        Texts::If(
+                                            Condition::TitleExist,
+                                            Box::new(Texts::Parts(vec![
+                                                Texts::Styled(
+                                                    vec![AddStyle::Fg(Color::Indexed(149))],
+                                                    Box::new(Texts::CurrentTitle),
+                                                ),
+                                                Texts::If(
+                                                    Condition::ArtistExist,
+                                                    Box::new(Texts::Parts(vec![
+                                                        Texts::Styled(
+                                                            vec![AddStyle::Fg(Color::Indexed(216))],
+                                                            Box::new(Texts::Text(String::from(
+                                                                " ◆ ",
+                                                            ))),
+                                                        ),
+                                                        Texts::Styled(
+                                                            vec![AddStyle::Fg(Color::Indexed(185))],
+                                                            Box::new(Texts::CurrentArtist),
+                                                        ),
+                                                        Texts::If(
+                                                            Condition::AlbumExist,
+                                                            Box::new(Texts::Parts(vec![
+                                                                Texts::Styled(
+                                                                    vec![AddStyle::Fg(
+                                                                        Color::Indexed(216),
+                                                                    )],
+                                                                    Box::new(Texts::Text(
+                                                                        String::from(" ◆ "),
+                                                                    )),
+                                                                ),
+                                                                Texts::Styled(
+                                                                    vec![AddStyle::Fg(
+                                                                        Color::Indexed(221),
+                                                                    )],
+                                                                    Box::new(Texts::CurrentAlbum),
+                                                                ),
+                                                            ])),
+                                                            None,
+                                                        ),
+                                                    ])),
+                                                    None,
+                                                ),
+                                            ])),
+                                            Some(Box::new(Texts::Styled(
+                                                vec![AddStyle::Fg(Color::Indexed(185))],
+                                                Box::new(Texts::CurrentFile),
+                                            ))),
+                                        ),
+                                    ])),
+                                    None,
+                                ))),
                             ))),
                         )),
                     )),
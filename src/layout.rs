@@ -4,47 +4,80 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{List, ListItem, Paragraph},
+    widgets::{List, ListItem, ListState, Paragraph},
     Frame, Terminal,
 };
 
 use crate::{
-    app::State,
-    config::{AddStyle, Column, Condition, Constrained, Texts, Widget},
-    mpd::{PlayerState, Song, Status, Track},
+    app::{self, Message, MessageLevel, State},
+    config::{
+        AddStyle, Column, Condition, Config, Constrained, Texts, TimeFormat, TimeFormats, TimePart,
+        View, Widget,
+    },
+    mpd::{Entry, Output, PlayerState, Track},
 };
 
-struct FlattenState<'a, 'b> {
-    status: &'a Status,
+#[derive(Clone, Copy)]
+struct FlattenState<'a> {
+    state: &'a State,
+    time_format: &'a TimeFormats,
     current_track: Option<&'a Track>,
     queue_track: Option<&'a Track>,
     queue_current: bool,
+    queue_next: bool,
     selected: bool,
-    searching: bool,
-    query: &'a str,
-    style: &'b Style,
+    marked: bool,
+    entry: Option<&'a Entry>,
+    group: Option<&'a [(String, String)]>,
+    output: Option<&'a Output>,
+    style: Style,
 }
 
 struct ConditionState<'a> {
-    status: &'a Status,
+    state: &'a State,
     current_track: Option<&'a Track>,
     queue_track: Option<&'a Track>,
     queue_current: bool,
+    queue_next: bool,
     selected: bool,
-    searching: bool,
-    query: &'a str,
+    marked: bool,
+    entry: Option<&'a Entry>,
+    output: Option<&'a Output>,
 }
 
-pub fn render(term: &mut Terminal<impl Backend>, widget: &Widget, s: &mut State) -> Result<()> {
+impl<'a> From<&FlattenState<'a>> for ConditionState<'a> {
+    fn from(s: &FlattenState<'a>) -> ConditionState<'a> {
+        ConditionState {
+            state: s.state,
+            current_track: s.current_track,
+            queue_track: s.queue_track,
+            queue_current: s.queue_current,
+            queue_next: s.queue_next,
+            selected: s.selected,
+            marked: s.marked,
+            entry: s.entry,
+            output: s.output,
+        }
+    }
+}
+
+pub fn render(term: &mut Terminal<impl Backend>, cfg: &Config, s: &mut State) -> Result<()> {
+    s.progress_areas.clear();
     term.draw(|frame| {
-        _render(frame, frame.size(), widget, s);
+        _render(frame, frame.size(), &cfg.layout, cfg, s);
     })
     .context("Failed to draw to terminal")?;
 
     Ok(())
 }
 
-fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut State) {
+fn _render(
+    frame: &mut Frame<impl Backend>,
+    size: Rect,
+    widget: &Widget,
+    cfg: &Config,
+    s: &mut State,
+) {
     match widget {
         Widget::Rows(xs) => {
             let len = xs.capacity();
@@ -79,7 +112,7 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
             let mut ws = ws.into_iter();
 
             while let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
-                _render(frame, *chunk, w, s);
+                _render(frame, *chunk, w, cfg, s);
             }
         }
         Widget::Columns(xs) => {
@@ -115,276 +148,576 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
             let mut ws = ws.into_iter();
 
             while let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
-                _render(frame, *chunk, w, s);
+                _render(frame, *chunk, w, cfg, s);
             }
         }
         Widget::Textbox(xs) => {
             frame.render_widget(
-                Paragraph::new(flatten(
-                    xs,
-                    &s.status,
-                    s.status
-                        .song
-                        .as_ref()
-                        .and_then(|song| s.queue.get(song.pos)),
-                    None,
-                    false,
-                    false,
-                    s.searching,
-                    &s.query,
-                )),
+                Paragraph::new(flatten(xs, &FlattenState::new(s, cfg))),
                 size,
             );
         }
         Widget::TextboxC(xs) => {
             frame.render_widget(
-                Paragraph::new(flatten(
-                    xs,
-                    &s.status,
-                    s.status
-                        .song
-                        .as_ref()
-                        .and_then(|song| s.queue.get(song.pos)),
-                    None,
-                    false,
-                    false,
-                    s.searching,
-                    &s.query,
-                ))
-                .alignment(Alignment::Center),
+                Paragraph::new(flatten(xs, &FlattenState::new(s, cfg)))
+                    .alignment(Alignment::Center),
                 size,
             );
         }
         Widget::TextboxR(xs) => {
             frame.render_widget(
-                Paragraph::new(flatten(
-                    xs,
-                    &s.status,
-                    s.status
-                        .song
-                        .as_ref()
-                        .and_then(|song| s.queue.get(song.pos)),
-                    None,
-                    false,
-                    false,
-                    s.searching,
-                    &s.query,
-                ))
-                .alignment(Alignment::Right),
+                Paragraph::new(flatten(xs, &FlattenState::new(s, cfg))).alignment(Alignment::Right),
                 size,
             );
         }
         Widget::Queue(xs) => {
-            let len = xs.capacity();
-            let mut ws = Vec::with_capacity(len);
-            let mut cs = Vec::with_capacity(len);
-
-            let len = s.queue.len();
-            if len == 0 {
+            if s.queue.is_empty() {
                 return;
             }
 
-            let denom = xs.iter().fold(0, |n, Column { item, .. }| {
-                if let Constrained::Ratio(m, _) = item {
-                    n + m
-                } else {
-                    n
-                }
+            let pos = s
+                .current_track()
+                .and(s.status.song.as_ref().map(|song| song.pos));
+            let next_pos = s.status.next_song.as_ref().and_then(|next| {
+                let track = s.queue.get(next.pos)?;
+                (track.id == Some(next.id)).then_some(next.pos)
             });
+            let base = FlattenState::new(s, cfg);
+            let visual_rows = s.visual_rows();
 
-            let (pos, current_track) = if let Some(Song { pos, .. }) = s.status.song {
-                (Some(pos), s.queue.get(pos))
-            } else {
-                (None, None)
-            };
-
-            for column in xs {
-                let (txts, constraint) = match &column.item {
-                    Constrained::Fixed(n, txts) => (txts, Constraint::Length(*n)),
-                    Constrained::Max(n, txts) => (txts, Constraint::Max(*n)),
-                    Constrained::Min(n, txts) => (txts, Constraint::Min(*n)),
-                    Constrained::Ratio(n, txts) => (txts, Constraint::Ratio(*n, denom)),
-                };
-
-                let mut items = Vec::with_capacity(len);
+            let mut liststate = s.liststate.clone();
+            render_columns(frame, size, xs, &mut liststate, |txts| {
+                let mut items = Vec::with_capacity(s.len());
                 if s.query.is_empty() {
                     for (i, track) in s.queue.iter().enumerate() {
                         items.push(ListItem::new(flatten(
                             txts,
-                            &s.status,
-                            current_track,
-                            Some(track),
-                            pos == Some(i),
-                            s.liststate.selected() == Some(i),
-                            s.searching,
-                            &s.query,
+                            &FlattenState {
+                                queue_track: Some(track),
+                                queue_current: pos == Some(i),
+                                queue_next: next_pos == Some(i),
+                                selected: s.liststate.selected() == Some(i),
+                                marked: s.is_marked(i, visual_rows.as_ref()),
+                                ..base
+                            },
                         )));
                     }
                 } else {
-                    for &i in &s.filtered {
+                    for (j, &i) in s.filtered.iter().enumerate() {
                         items.push(ListItem::new(flatten(
                             txts,
-                            &s.status,
-                            current_track,
-                            s.queue.get(i),
-                            pos == Some(i),
-                            s.liststate.selected() == Some(i),
-                            s.searching,
-                            &s.query,
+                            &FlattenState {
+                                queue_track: s.queue.get(i),
+                                queue_current: pos == Some(i),
+                                queue_next: next_pos == Some(i),
+                                selected: s.liststate.selected() == Some(j),
+                                marked: s.is_marked(j, visual_rows.as_ref()),
+                                ..base
+                            },
                         )));
                     }
                 }
-                ws.push(
-                    List::new(items)
-                        .style(patch_style(Style::default(), &column.style))
-                        .highlight_style(patch_style(Style::default(), &column.selected_style)),
-                );
-                cs.push(constraint);
+                items
+            });
+            s.liststate = liststate;
+        }
+        Widget::Browser(xs) => {
+            let liststate = render_entries(frame, size, xs, &s.browser.list, s, cfg);
+            s.browser.list.liststate = liststate;
+        }
+        Widget::Library(xs) => {
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(column_constraints(xs))
+                .split(size);
+
+            let base = FlattenState::new(s, cfg);
+            let library = &s.library;
+            let mut liststates = Vec::with_capacity(xs.len());
+            for (level, (column, chunk)) in xs.iter().zip(chunks.iter()).enumerate() {
+                let (Constrained::Max(_, txts)
+                | Constrained::Min(_, txts)
+                | Constrained::Fixed(_, txts)
+                | Constrained::Ratio(_, txts)) = &column.item;
+
+                let (items, mut liststate): (Vec<_>, _) =
+                    if let Some(groups) = library.groups.get(level) {
+                        (
+                            groups
+                                .items
+                                .iter()
+                                .enumerate()
+                                .map(|(i, group)| {
+                                    ListItem::new(flatten(
+                                        txts,
+                                        &FlattenState {
+                                            group: Some(group),
+                                            selected: groups.selected == i,
+                                            ..base
+                                        },
+                                    ))
+                                })
+                                .collect(),
+                            groups.liststate.clone(),
+                        )
+                    } else if level == library.groups.len() {
+                        (
+                            library
+                                .tracks
+                                .items
+                                .iter()
+                                .enumerate()
+                                .map(|(i, track)| {
+                                    ListItem::new(flatten(
+                                        txts,
+                                        &FlattenState {
+                                            queue_track: Some(track),
+                                            selected: library.tracks.selected == i,
+                                            ..base
+                                        },
+                                    ))
+                                })
+                                .collect(),
+                            library.tracks.liststate.clone(),
+                        )
+                    } else {
+                        break;
+                    };
+
+                if !items.is_empty() {
+                    frame.render_stateful_widget(
+                        List::new(items)
+                            .style(patch_style(Style::default(), &column.style))
+                            .highlight_style(patch_style(Style::default(), &column.selected_style)),
+                        *chunk,
+                        &mut liststate,
+                    );
+                }
+                liststates.push(liststate);
             }
 
-            let layout = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints(cs);
+            for (level, liststate) in liststates.into_iter().enumerate() {
+                if let Some(groups) = s.library.groups.get_mut(level) {
+                    groups.liststate = liststate;
+                } else {
+                    s.library.tracks.liststate = liststate;
+                }
+            }
+        }
+        Widget::Search(xs) => {
+            let list = &s.search.list;
+            if list.items.is_empty() {
+                return;
+            }
 
-            let chunks = layout.split(size);
-            let mut chunks = chunks.iter();
-            let mut ws = ws.into_iter();
+            let base = FlattenState::new(s, cfg);
+            let mut liststate = list.liststate.clone();
+            render_columns(frame, size, xs, &mut liststate, |txts| {
+                list.items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, track)| {
+                        ListItem::new(flatten(
+                            txts,
+                            &FlattenState {
+                                queue_track: Some(track),
+                                selected: list.selected == i,
+                                ..base
+                            },
+                        ))
+                    })
+                    .collect()
+            });
+            s.search.list.liststate = liststate;
+        }
+        Widget::Playlists(xs) => {
+            let liststate = render_entries(frame, size, xs, &s.playlists.list, s, cfg);
+            s.playlists.list.liststate = liststate;
+        }
+        Widget::Outputs(xs) => {
+            let list = &s.outputs;
+            if list.items.is_empty() {
+                return;
+            }
 
-            if let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
-                frame.render_stateful_widget(w, *chunk, &mut s.liststate);
-                while let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
-                    frame.render_stateful_widget(w, *chunk, &mut s.liststate.clone());
+            let base = FlattenState::new(s, cfg);
+            let mut liststate = list.liststate.clone();
+            render_columns(frame, size, xs, &mut liststate, |txts| {
+                list.items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, output)| {
+                        ListItem::new(flatten(
+                            txts,
+                            &FlattenState {
+                                output: Some(output),
+                                selected: list.selected == i,
+                                ..base
+                            },
+                        ))
+                    })
+                    .collect()
+            });
+            s.outputs.liststate = liststate;
+        }
+        Widget::If(cond, x, y) => {
+            let s1 = FlattenState::new(s, cfg);
+            if eval_cond(cond, &ConditionState::from(&s1)) {
+                _render(frame, size, x, cfg, s);
+            } else if let Some(y) = y {
+                _render(frame, size, y, cfg, s);
+            }
+        }
+        Widget::Progress {
+            filled,
+            empty,
+            style,
+            empty_style,
+            label,
+        } => {
+            s.progress_areas.push(size);
+
+            let ratio = match (s.elapsed(), s.duration()) {
+                (Some(elapsed), Some(duration)) if duration > 0.0 => {
+                    (elapsed / duration).clamp(0.0, 1.0)
                 }
+                _ => 0.0,
+            };
+            // the strings can be wider than a column, only repeat them as many times as they fit
+            let width = usize::from(size.width);
+            let filled_width = Span::raw(filled.as_str()).width().max(1);
+            let empty_width = Span::raw(empty.as_str()).width().max(1);
+            let n = (ratio * width as f64).round() as usize / filled_width;
+            let m = (width - n * filled_width) / empty_width;
+
+            frame.render_widget(
+                Paragraph::new(Line::from(vec![
+                    Span::styled(filled.repeat(n), patch_style(Style::default(), style)),
+                    Span::styled(empty.repeat(m), patch_style(Style::default(), empty_style)),
+                ])),
+                size,
+            );
+            if let Some(label) = label {
+                frame.render_widget(
+                    Paragraph::new(flatten(label, &FlattenState::new(s, cfg)))
+                        .alignment(Alignment::Center),
+                    size,
+                );
             }
         }
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn flatten<'a>(
-    xs: &'a Texts,
-    status: &'a Status,
-    current_track: Option<&'a Track>,
-    queue_track: Option<&'a Track>,
-    queue_current: bool,
-    selected: bool,
-    searching: bool,
-    query: &'a str,
-) -> Line<'a> {
+/// The views in the layout that can be focused, in order
+pub fn views(widget: &Widget) -> Vec<View> {
+    fn walk(widget: &Widget, views: &mut Vec<View>) {
+        let view = match widget {
+            Widget::Rows(xs) | Widget::Columns(xs) => {
+                for x in xs {
+                    let (Constrained::Max(_, w)
+                    | Constrained::Min(_, w)
+                    | Constrained::Fixed(_, w)
+                    | Constrained::Ratio(_, w)) = x;
+                    walk(w, views);
+                }
+                return;
+            }
+            Widget::If(_, x, y) => {
+                walk(x, views);
+                if let Some(y) = y {
+                    walk(y, views);
+                }
+                return;
+            }
+            Widget::Queue(_) => View::Queue,
+            Widget::Browser(_) => View::Browser,
+            Widget::Library(_) => View::Library,
+            Widget::Search(_) => View::Search,
+            Widget::Playlists(_) => View::Playlists,
+            Widget::Outputs(_) => View::Outputs,
+            _ => return,
+        };
+        if !views.contains(&view) {
+            views.push(view);
+        }
+    }
+
+    let mut views = Vec::new();
+    walk(widget, &mut views);
+    views
+}
+
+/// Renders a list split into columns, each column shares the same selection
+fn render_columns<'a>(
+    frame: &mut Frame<impl Backend>,
+    size: Rect,
+    xs: &'a [Column],
+    liststate: &mut ListState,
+    mut items: impl FnMut(&'a Texts) -> Vec<ListItem<'a>>,
+) {
+    let mut ws = Vec::with_capacity(xs.len());
+    for column in xs {
+        let (Constrained::Max(_, txts)
+        | Constrained::Min(_, txts)
+        | Constrained::Fixed(_, txts)
+        | Constrained::Ratio(_, txts)) = &column.item;
+
+        ws.push(
+            List::new(items(txts))
+                .style(patch_style(Style::default(), &column.style))
+                .highlight_style(patch_style(Style::default(), &column.selected_style)),
+        );
+    }
+
+    let layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(column_constraints(xs));
+
+    let chunks = layout.split(size);
+    let mut chunks = chunks.iter();
+    let mut ws = ws.into_iter();
+
+    if let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
+        frame.render_stateful_widget(w, *chunk, liststate);
+        while let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
+            frame.render_stateful_widget(w, *chunk, &mut liststate.clone());
+        }
+    }
+}
+
+/// Renders directories, songs and playlists, returns the new state of the list
+fn render_entries(
+    frame: &mut Frame<impl Backend>,
+    size: Rect,
+    xs: &[Column],
+    list: &app::List<Entry>,
+    s: &State,
+    cfg: &Config,
+) -> ListState {
+    let mut liststate = list.liststate.clone();
+    if list.items.is_empty() {
+        return liststate;
+    }
+
+    let base = FlattenState::new(s, cfg);
+    render_columns(frame, size, xs, &mut liststate, |txts| {
+        list.items
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                ListItem::new(flatten(
+                    txts,
+                    &FlattenState {
+                        queue_track: match entry {
+                            Entry::Song(track) => Some(track),
+                            _ => None,
+                        },
+                        entry: Some(entry),
+                        selected: list.selected == i,
+                        ..base
+                    },
+                ))
+            })
+            .collect()
+    });
+    liststate
+}
+
+fn column_constraints(xs: &[Column]) -> Vec<Constraint> {
+    let denom = xs.iter().fold(0, |n, Column { item, .. }| {
+        if let Constrained::Ratio(m, _) = item {
+            n + m
+        } else {
+            n
+        }
+    });
+
+    xs.iter()
+        .map(|column| match column.item {
+            Constrained::Fixed(n, _) => Constraint::Length(n),
+            Constrained::Max(n, _) => Constraint::Max(n),
+            Constrained::Min(n, _) => Constraint::Min(n),
+            Constrained::Ratio(n, _) => Constraint::Ratio(n, denom),
+        })
+        .collect()
+}
+
+impl<'a> FlattenState<'a> {
+    fn new(state: &'a State, cfg: &'a Config) -> FlattenState<'a> {
+        FlattenState {
+            state,
+            time_format: &cfg.time_format,
+            current_track: state.current_track(),
+            queue_track: None,
+            queue_current: false,
+            queue_next: false,
+            selected: false,
+            marked: false,
+            entry: None,
+            group: None,
+            output: None,
+            style: Style::default(),
+        }
+    }
+}
+
+fn flatten<'a>(xs: &'a Texts, s: &FlattenState<'a>) -> Line<'a> {
     let mut spans = Vec::new();
-    _flatten(
-        &mut spans,
-        xs,
-        &FlattenState {
-            status,
-            current_track,
-            queue_track,
-            queue_current,
-            selected,
-            searching,
-            query,
-            style: &Style::default(),
-        },
-    );
+    _flatten(&mut spans, xs, s);
     spans.into()
 }
 
-fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '_>) {
+fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a>) {
     match xs {
-        Texts::Text(x) => spans.push(Span::styled(x, *s.style)),
+        Texts::Text(x) => spans.push(Span::styled(x, s.style)),
         Texts::CurrentElapsed => {
-            if let Some(Song { elapsed, .. }) = s.status.song {
+            if let Some(elapsed) = s.state.elapsed() {
+                let long = s.state.duration().unwrap_or_default().max(elapsed) >= 3600.0;
                 spans.push(Span::styled(
-                    format!("{}:{:02}", elapsed / 60, elapsed % 60),
-                    *s.style,
+                    format_time(elapsed, long, s.time_format),
+                    s.style,
                 ));
             }
         }
         Texts::CurrentDuration => {
-            if let Some(Track { time, .. }) = s.current_track {
+            if let Some(duration) = s.current_track.and(s.state.duration()) {
                 spans.push(Span::styled(
-                    format!("{}:{:02}", time / 60, time % 60),
-                    *s.style,
+                    format_time(duration, duration >= 3600.0, s.time_format),
+                    s.style,
                 ));
             }
         }
         Texts::CurrentFile => {
             if let Some(Track { file, .. }) = s.current_track {
-                spans.push(Span::styled(file, *s.style));
+                spans.push(Span::styled(file, s.style));
             }
         }
         Texts::CurrentTitle => {
-            if let Some(Track {
-                title: Some(title), ..
-            }) = s.current_track
-            {
-                spans.push(Span::styled(title, *s.style));
+            if let Some(track) = s.current_track {
+                push_tag(spans, track, "Title", s.style);
             }
         }
         Texts::CurrentArtist => {
-            if let Some(Track {
-                artist: Some(artist),
-                ..
-            }) = s.current_track
-            {
-                spans.push(Span::styled(artist, *s.style));
+            if let Some(track) = s.current_track {
+                push_tag(spans, track, "Artist", s.style);
             }
         }
         Texts::CurrentAlbum => {
-            if let Some(Track {
-                album: Some(album), ..
-            }) = s.current_track
-            {
-                spans.push(Span::styled(album, *s.style));
+            if let Some(track) = s.current_track {
+                push_tag(spans, track, "Album", s.style);
             }
         }
         Texts::QueueDuration => {
-            if let Some(Track { time, .. }) = s.queue_track {
+            if let Some(Track {
+                duration: Some(duration),
+                ..
+            }) = s.queue_track
+            {
                 spans.push(Span::styled(
-                    format!("{}:{:02}", time / 60, time % 60),
-                    *s.style,
+                    format_time(*duration, *duration >= 3600.0, s.time_format),
+                    s.style,
                 ));
             }
         }
         Texts::QueueFile => {
             if let Some(Track { file, .. }) = s.queue_track {
-                spans.push(Span::styled(file, *s.style));
+                spans.push(Span::styled(file, s.style));
             }
         }
         Texts::QueueTitle => {
-            if let Some(Track {
-                title: Some(title), ..
-            }) = s.queue_track
-            {
-                spans.push(Span::styled(title, *s.style));
+            if let Some(track) = s.queue_track {
+                push_tag(spans, track, "Title", s.style);
             }
         }
         Texts::QueueArtist => {
-            if let Some(Track {
-                artist: Some(artist),
-                ..
-            }) = s.queue_track
-            {
-                spans.push(Span::styled(artist, *s.style));
+            if let Some(track) = s.queue_track {
+                push_tag(spans, track, "Artist", s.style);
             }
         }
         Texts::QueueAlbum => {
-            if let Some(Track {
-                album: Some(album), ..
-            }) = s.queue_track
+            if let Some(track) = s.queue_track {
+                push_tag(spans, track, "Album", s.style);
+            }
+        }
+        Texts::CurrentTag(name) => {
+            if let Some(track) = s.current_track {
+                push_tag(spans, track, name, s.style);
+            }
+        }
+        Texts::QueueTag(name) => {
+            if let Some(track) = s.queue_track {
+                push_tag(spans, track, name, s.style);
+            }
+        }
+        Texts::QueuePriority => {
+            if let Some(track) = s.queue_track {
+                spans.push(Span::styled(track.priority.to_string(), s.style));
+            }
+        }
+        Texts::EntryName => {
+            if let Some(entry) = s.entry {
+                spans.push(Span::styled(entry.name(), s.style));
+            }
+        }
+        Texts::BrowserPath => {
+            spans.push(Span::styled(&s.state.browser.path, s.style));
+        }
+        Texts::LibraryTag(name) => {
+            if let Some((_, value)) = s
+                .group
+                .and_then(|group| group.iter().find(|(tag, _)| tag.eq_ignore_ascii_case(name)))
             {
-                spans.push(Span::styled(album, *s.style));
+                spans.push(Span::styled(value, s.style));
             }
         }
         Texts::Query => {
-            spans.push(Span::styled(String::from(s.query), *s.style));
+            spans.push(Span::styled(&s.state.query, s.style));
+        }
+        Texts::SearchQuery => {
+            spans.push(Span::styled(&s.state.search.query, s.style));
+        }
+        Texts::PlaylistName => {
+            spans.push(Span::styled(&s.state.playlists.path, s.style));
+        }
+        Texts::OutputName => {
+            if let Some(output) = s.output {
+                spans.push(Span::styled(&output.name, s.style));
+            }
+        }
+        Texts::OutputPlugin => {
+            if let Some(output) = s.output {
+                spans.push(Span::styled(&output.plugin, s.style));
+            }
+        }
+        Texts::PendingKeys => {
+            spans.push(Span::styled(&s.state.pending_keys, s.style));
+        }
+        Texts::Prompt => {
+            if let Some(prompt) = &s.state.prompt {
+                spans.push(Span::styled(prompt.label(), s.style));
+            }
+        }
+        Texts::PromptInput => {
+            if let Some(prompt) = &s.state.prompt {
+                spans.push(Span::styled(&prompt.input, s.style));
+            }
+        }
+        Texts::Volume => {
+            if let Some(volume) = s.state.status.volume {
+                spans.push(Span::styled(volume.to_string(), s.style));
+            }
+        }
+        Texts::Message => {
+            if let Some(Message { text, .. }) = &s.state.message {
+                spans.push(Span::styled(text, s.style));
+            }
         }
         Texts::Styled(styles, xs) => {
             _flatten(
                 spans,
                 xs,
                 &FlattenState {
-                    style: &patch_style(*s.style, styles),
+                    style: patch_style(s.style, styles),
                     ..*s
                 },
             );
@@ -397,18 +730,7 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
         Texts::If(cond, xs, Some(ys)) => {
             _flatten(
                 spans,
-                if eval_cond(
-                    cond,
-                    &ConditionState {
-                        status: s.status,
-                        current_track: s.current_track,
-                        queue_track: s.queue_track,
-                        queue_current: s.queue_current,
-                        selected: s.selected,
-                        searching: s.searching,
-                        query: s.query,
-                    },
-                ) {
+                if eval_cond(cond, &ConditionState::from(s)) {
                     xs
                 } else {
                     ys
@@ -417,24 +739,61 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
             );
         }
         Texts::If(cond, xs, None) => {
-            if eval_cond(
-                cond,
-                &ConditionState {
-                    status: s.status,
-                    current_track: s.current_track,
-                    queue_track: s.queue_track,
-                    queue_current: s.queue_current,
-                    selected: s.selected,
-                    searching: s.searching,
-                    query: s.query,
-                },
-            ) {
+            if eval_cond(cond, &ConditionState::from(s)) {
                 _flatten(spans, xs, s);
             }
         }
     }
 }
 
+fn push_tag<'a>(spans: &mut Vec<Span<'a>>, track: &'a Track, name: &str, style: Style) {
+    let mut values = track.tags(name);
+    if let Some(first) = values.next() {
+        if let Some(second) = values.next() {
+            let mut value = format!("{first}, {second}");
+            for x in values {
+                value.push_str(", ");
+                value.push_str(x);
+            }
+            spans.push(Span::styled(value, style));
+        } else {
+            spans.push(Span::styled(first, style));
+        }
+    }
+}
+
+fn format_time(secs: f64, long: bool, time_format: &TimeFormats) -> String {
+    let TimeFormat(parts) = if long {
+        &time_format.long
+    } else {
+        &time_format.short
+    };
+    let hours = parts.iter().any(|part| matches!(part, TimePart::Hours));
+    let minutes = hours
+        || parts
+            .iter()
+            .any(|part| matches!(part, TimePart::Minutes | TimePart::MinutesPadded));
+
+    let tenths = (secs.max(0.0) * 10.0) as u64;
+    let secs = tenths / 10;
+    let m = if hours { secs / 60 % 60 } else { secs / 60 };
+    let s = if minutes { secs % 60 } else { secs };
+
+    let mut time = String::with_capacity(8);
+    for part in parts {
+        match part {
+            TimePart::Text(text) => time.push_str(text),
+            TimePart::Hours => time.push_str(&(secs / 3600).to_string()),
+            TimePart::Minutes => time.push_str(&m.to_string()),
+            TimePart::MinutesPadded => time.push_str(&format!("{m:02}")),
+            TimePart::Seconds => time.push_str(&s.to_string()),
+            TimePart::SecondsPadded => time.push_str(&format!("{s:02}")),
+            TimePart::Tenths => time.push_str(&(tenths % 10).to_string()),
+        }
+    }
+    time
+}
+
 fn patch_style(style: Style, styles: &[AddStyle]) -> Style {
     let mut style = style;
     for add_style in styles {
@@ -506,31 +865,71 @@ fn patch_style(style: Style, styles: &[AddStyle]) -> Style {
 
 fn eval_cond(cond: &Condition, s: &ConditionState) -> bool {
     match cond {
-        Condition::Repeat => s.status.repeat,
-        Condition::Random => s.status.random,
-        Condition::Single => s.status.single == Some(true),
-        Condition::Oneshot => s.status.single.is_none(),
-        Condition::Consume => s.status.consume,
-        Condition::Playing => s.status.state == PlayerState::Play,
-        Condition::Paused => s.status.state == PlayerState::Pause,
-        Condition::Stopped => s.status.state == PlayerState::Stop,
-        Condition::TitleExist => matches!(s.current_track, Some(Track { title: Some(_), .. })),
-        Condition::ArtistExist => matches!(
-            s.current_track,
-            Some(Track {
-                artist: Some(_),
+        Condition::Repeat => s.state.status.repeat,
+        Condition::Random => s.state.status.random,
+        Condition::Single => s.state.status.single == Some(true),
+        Condition::Oneshot => s.state.status.single.is_none(),
+        Condition::Consume => s.state.status.consume,
+        Condition::Muted => s.state.status.volume == Some(0),
+        Condition::Playing => s.state.status.state == PlayerState::Play,
+        Condition::Paused => s.state.status.state == PlayerState::Pause,
+        Condition::Stopped => s.state.status.state == PlayerState::Stop,
+        Condition::TitleExist => tag_exists(s.current_track, "Title"),
+        Condition::ArtistExist => tag_exists(s.current_track, "Artist"),
+        Condition::AlbumExist => tag_exists(s.current_track, "Album"),
+        Condition::CurrentTagExist(name) => tag_exists(s.current_track, name),
+        Condition::QueueTitleExist => tag_exists(s.queue_track, "Title"),
+        Condition::QueueTagExist(name) => tag_exists(s.queue_track, name),
+        Condition::QueueCurrent => s.queue_current,
+        Condition::QueueNext => s.queue_next,
+        Condition::Selected => s.selected,
+        Condition::Marked => s.marked,
+        Condition::Visual => s.state.visual.is_some(),
+        Condition::EntryDirectory => matches!(s.entry, Some(Entry::Directory(_))),
+        Condition::EntrySong => matches!(s.entry, Some(Entry::Song(_))),
+        Condition::EntryPlaylist => matches!(s.entry, Some(Entry::Playlist(_))),
+        Condition::OutputEnabled => s.output.is_some_and(|output| output.enabled),
+        Condition::Focused(view) => s.state.focus == *view,
+        Condition::Searching => s.state.searching,
+        Condition::SearchingLibrary => s.state.search.editing,
+        Condition::Prompting => s.state.prompt.is_some(),
+        Condition::Filtered => !s.state.query.is_empty(),
+        Condition::Connected => s.state.connected,
+        Condition::HasMessage => s.state.message.is_some(),
+        Condition::HasError => matches!(
+            &s.state.message,
+            Some(Message {
+                level: MessageLevel::Error,
                 ..
             })
         ),
-        Condition::AlbumExist => matches!(s.current_track, Some(Track { album: Some(_), .. })),
-        Condition::QueueTitleExist => matches!(s.queue_track, Some(Track { title: Some(_), .. })),
-        Condition::QueueCurrent => s.queue_current,
-        Condition::Selected => s.selected,
-        Condition::Searching => s.searching,
-        Condition::Filtered => !s.query.is_empty(),
         Condition::Not(x) => !eval_cond(x, s),
         Condition::And(x, y) => eval_cond(x, s) && eval_cond(y, s),
         Condition::Or(x, y) => eval_cond(x, s) || eval_cond(y, s),
         Condition::Xor(x, y) => eval_cond(x, s) ^ eval_cond(y, s),
     }
 }
+
+fn tag_exists(track: Option<&Track>, name: &str) -> bool {
+    track.is_some_and(|track| track.tag(name).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_time;
+    use crate::config::TimeFormats;
+
+    #[test]
+    fn times() {
+        let time_format: TimeFormats =
+            ron::from_str(r#"(short: "{s}s", long: "{h}:{mm}:{ss}.{f}")"#).unwrap();
+        // without minutes in the format, seconds are the total seconds
+        assert_eq!(format_time(125.9, false, &time_format), "125s");
+        assert_eq!(format_time(65000.25, false, &time_format), "65000s");
+        assert_eq!(format_time(65000.25, true, &time_format), "18:03:20.2");
+        assert_eq!(format_time(-1.0, true, &time_format), "0:00:00.0");
+
+        let time_format: TimeFormats = ron::from_str(r#"(short: "{m}:{ss}")"#).unwrap();
+        assert_eq!(format_time(65000.0, false, &time_format), "1083:20");
+    }
+}
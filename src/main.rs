@@ -2,49 +2,76 @@
 
 mod app;
 mod cli;
+mod cmdline;
 mod config;
 mod defaults;
 mod layout;
 mod mpd;
 
 use std::{
-    cmp::min,
+    collections::HashSet,
     env, fs,
     io::stdout,
-    process::exit,
+    mem,
+    path::Path,
     sync::{
-        atomic::{AtomicU8, Ordering},
+        atomic::{AtomicBool, AtomicU8, Ordering},
         Arc,
     },
     thread::{self, Thread},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use async_io::{block_on, Timer};
 use async_net::resolve;
 use clap::Parser;
 use crossbeam_queue::SegQueue;
 use crossterm::{
     event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
-        MouseEvent, MouseEventKind,
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers, MouseEvent,
     },
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
 use dirs::config_dir;
-use futures_lite::StreamExt;
+use futures_lite::{future, StreamExt};
 use ratatui::{backend::CrosstermBackend, widgets::ListState, Terminal};
 use secular::lower_lay_string;
 
 use crate::{
-    app::{Command, State},
+    app::{Browser, Command, Cursor, Library, List, Prompt, PromptKind, Search, State},
     cli::Opts,
-    layout::render,
-    mpd::{Client, PlayerState},
+    cmdline::{Editor, Line, Settings, Target, COMMANDS, SETTINGS},
+    config::{keys_string, Chord, View},
+    layout::{render, views},
+    mpd::{
+        check_command, expand, filter_expr, quote, search_expr, Address, Backoff, Client, Entry,
+        MpdError, PlayerState, Subsystem,
+    },
 };
 
+// mpd closes idle client connections after 60 seconds by default
+const STATUS_RESYNC_INTERVAL: Duration = Duration::from_secs(30);
+// the main thread reconnects, an unreachable host must not freeze the interface
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// what the main loop has to do next, set by the commands and the other threads
+const UPDATE_FRAME: u8 = 0b000_0001;
+const UPDATE_QUEUE: u8 = 0b000_0010;
+const UPDATE_STATUS: u8 = 0b000_0100;
+const RECONNECT: u8 = 0b000_1000;
+const UPDATE_PLAYLISTS: u8 = 0b001_0000;
+const UPDATE_DATABASE: u8 = 0b010_0000;
+const UPDATE_OUTPUTS: u8 = 0b100_0000;
+// everything that can be stale after reconnecting
+const UPDATE_ALL: u8 = UPDATE_FRAME
+    | UPDATE_QUEUE
+    | UPDATE_STATUS
+    | UPDATE_PLAYLISTS
+    | UPDATE_DATABASE
+    | UPDATE_OUTPUTS;
+
 fn cleanup() {
     let mut stdout = stdout();
 
@@ -74,6 +101,33 @@ impl Drop for Cleanup {
     }
 }
 
+/// The count and the keys typed so far of an incomplete binding
+fn pending_keys(count: Option<usize>, keys: &[Chord]) -> String {
+    let mut pending = count.map_or_else(String::new, |count| count.to_string());
+    pending.push_str(&keys_string(keys));
+    pending
+}
+
+/// The characters of keys that didn't complete a binding, which are typed into the query instead
+fn typed_chars(keys: &[Chord]) -> Vec<char> {
+    keys.iter()
+        .filter_map(|chord| match *chord {
+            Chord::Key(KeyCode::Char(c), modifiers)
+                if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                Some(c)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn read_password(file: &Path) -> Result<String> {
+    let password = fs::read_to_string(file)
+        .with_context(|| format!("Failed to read password file {}", file.display()))?;
+    Ok(password.trim_end_matches(['\r', '\n']).into())
+}
+
 fn main() -> Result<()> {
     block_on(run())
 }
@@ -81,7 +135,7 @@ fn main() -> Result<()> {
 async fn run() -> Result<()> {
     let opts = Opts::parse();
 
-    let cfg = if let Some(file) = opts.config {
+    let mut cfg = if let Some(file) = opts.config {
         ron::de::from_bytes(
             &fs::read(&file).with_context(|| format!("Failed to read file {}", file.display()))?,
         )
@@ -101,25 +155,56 @@ async fn run() -> Result<()> {
         defaults::config()
     };
 
-    let addr = &*if let Some(addr) = opts.address {
-        resolve(addr).await?
-    } else if let (Ok(host), Ok(port)) = (env::var("MPD_HOST"), env::var("MPD_PORT")) {
-        resolve((host, port.parse()?)).await?
+    let mut env_password = None;
+    let addr = &if let Some(addr) = opts.address {
+        Address::resolve(addr).await?
+    } else if let Ok(host) = env::var("MPD_HOST") {
+        let host = if let Some((password, host)) = host.split_once('@') {
+            env_password = Some(password.into());
+            host.into()
+        } else {
+            host
+        };
+
+        if host.starts_with(['/', '~']) {
+            Address::resolve(host).await?
+        } else {
+            // mpd's default port, so a password from MPD_HOST is only sent to that host
+            let port = env::var("MPD_PORT").map_or(Ok(6600), |port| port.parse())?;
+            Address::Tcp(resolve((host, port)).await?)
+        }
     } else {
-        resolve(cfg.address).await?
+        Address::resolve(cfg.address.clone()).await?
     };
 
-    let mut cl = Client::init(addr).await?;
+    let password = &if let Some(password) = opts.password {
+        Some(password)
+    } else if let Some(file) = opts.password_file {
+        Some(read_password(&file)?)
+    } else if env_password.is_some() {
+        env_password
+    } else if cfg.password.is_some() {
+        cfg.password.clone()
+    } else if let Some(file) = &cfg.password_file {
+        Some(read_password(file)?)
+    } else {
+        None
+    };
+
+    let mut cl = Client::init(addr, password.as_deref()).await?;
     if let Some(cmd) = opts.cmd {
         for cmd in cmd {
-            cl.command_stdout(&cmd).await?;
+            cl.command_stdout(&cmd).await.with_context(|| {
+                format!("Failed to run command {}", String::from_utf8_lossy(&cmd))
+            })?;
         }
         return Ok(());
     }
 
     let status = cl.status().await?;
-    let mut idle_cl = Client::init(addr).await?;
+    let mut idle_cl = Client::init(addr, password.as_deref()).await?;
     let (queue, mut queue_strings) = idle_cl.queue(status.queue_len, &cfg.search_fields).await?;
+    let views = views(&cfg.layout);
     let mut s = State {
         selected: 0,
         status,
@@ -128,8 +213,36 @@ async fn run() -> Result<()> {
         searching: false,
         query: String::with_capacity(32),
         filtered: Vec::new(),
+        marked: HashSet::new(),
+        focus: views.first().copied().unwrap_or(View::Queue),
+        browser: Browser::new(),
+        library: Library::new(cfg.library.len()),
+        search: Search::new(),
+        playlists: Browser::new(),
+        prompt: None,
+        history: Vec::new(),
+        outputs: List::new(),
+        pending_keys: String::new(),
+        visual: None,
+        status_time: Instant::now(),
+        connected: true,
+        message: None,
+        message_timeout: Duration::from_secs_f32(cfg.message_timeout),
+        progress_areas: Vec::new(),
     };
     s.reselect();
+    if views.contains(&View::Browser) {
+        s.browser.list.set_items(cl.ls("").await?);
+    }
+    if views.contains(&View::Playlists) {
+        s.playlists.list.set_items(cl.playlists().await?);
+    }
+    if views.contains(&View::Library) {
+        s.library.load(&mut cl, &cfg.library, 0).await?;
+    }
+    if views.contains(&View::Outputs) {
+        s.outputs.set_items(cl.outputs().await?);
+    }
 
     enable_raw_mode().context("Failed to enable raw mode")?;
     let mut stdout = stdout();
@@ -143,7 +256,7 @@ async fn run() -> Result<()> {
     let mut term =
         Terminal::new(CrosstermBackend::new(stdout)).context("Failed to initialize terminal")?;
 
-    render(&mut term, &cfg.layout, &mut s)?;
+    render(&mut term, &cfg, &mut s)?;
 
     let clear_query_on_play = opts.clear_query_on_play
         || if opts.no_clear_query_on_play {
@@ -151,42 +264,99 @@ async fn run() -> Result<()> {
         } else {
             cfg.clear_query_on_play
         };
-    let cycle = opts.cycle || if opts.no_cycle { false } else { cfg.cycle };
-    let jump_lines = opts.jump_lines.unwrap_or(cfg.jump_lines);
-    let seek_secs = opts.seek_secs.unwrap_or(cfg.seek_secs);
-
-    let seek_backwards = format!("seekcur -{seek_secs}");
-    let seek_backwards = seek_backwards.as_bytes();
-    let seek_forwards = format!("seekcur +{seek_secs}");
-    let seek_forwards = seek_forwards.as_bytes();
+    let mut settings = Settings {
+        cycle: opts.cycle || if opts.no_cycle { false } else { cfg.cycle },
+        jump_lines: opts.jump_lines.unwrap_or(cfg.jump_lines),
+        seek_secs: opts.seek_secs.unwrap_or(cfg.seek_secs),
+        volume_step: opts.volume_step.unwrap_or(cfg.volume_step),
+    };
+
     let update_interval = Duration::from_secs_f32(1.0 / opts.ups.unwrap_or(cfg.ups));
 
     let t1 = thread::current();
     let t2 = Thread::clone(&t1);
     let t3 = Thread::clone(&t1);
-    // update status: 0b100
-    // update queue:  0b010
-    // update frame:  0b001
-    let updates = Arc::new(AtomicU8::new(0b000));
+    let updates = Arc::new(AtomicU8::new(0));
     let updates1 = Arc::clone(&updates);
     let updates2 = Arc::clone(&updates);
     let updates3 = Arc::clone(&updates);
     let cmds = Arc::new(SegQueue::new());
     let cmds1 = Arc::clone(&cmds);
+    // whether keys are typed into a prompt or a query, the main thread can open and cancel prompts too
+    let text_input = Arc::new(AtomicBool::new(false));
+    let text_input1 = Arc::clone(&text_input);
+    let idle_addr = addr.clone();
+    let idle_password = password.clone();
 
     thread::spawn(move || {
         block_on(async move {
+            // only reset after idling succeeds, a connection that fails right away is still backed off
+            let mut backoff = Backoff::new();
+            let mut subsystems = Subsystem::ALL.to_vec();
             loop {
                 updates1.fetch_or(
-                    match idle_cl.idle().await {
-                        Ok((true, true)) => 0b111,
-                        Ok((true, false)) => 0b101,
-                        Ok((false, true)) => 0b011,
-                        Ok(_) => continue,
-                        Err(e) => {
-                            eprintln!("{e:?}");
-                            exit(1);
+                    match idle_cl.idle(&subsystems).await {
+                        Ok(changes) => {
+                            backoff.reset();
+                            let mut updates = 0;
+                            for (subsystem, update) in [
+                                (Subsystem::Options, UPDATE_STATUS | UPDATE_FRAME),
+                                (Subsystem::Player, UPDATE_STATUS | UPDATE_FRAME),
+                                (Subsystem::Mixer, UPDATE_STATUS | UPDATE_FRAME),
+                                (Subsystem::Update, UPDATE_STATUS | UPDATE_FRAME),
+                                (
+                                    Subsystem::Playlist,
+                                    UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME,
+                                ),
+                                (Subsystem::StoredPlaylist, UPDATE_PLAYLISTS | UPDATE_FRAME),
+                                (Subsystem::Database, UPDATE_DATABASE | UPDATE_FRAME),
+                                (Subsystem::Output, UPDATE_OUTPUTS | UPDATE_FRAME),
+                                // the partitions changed, mpd may have switched this client to another one
+                                (Subsystem::Partition, UPDATE_ALL),
+                            ] {
+                                if changes.contains(subsystem) {
+                                    updates |= update;
+                                }
+                            }
+                            if updates == 0 {
+                                continue;
+                            }
+                            updates
                         }
+                        Err(e) => match e.downcast_ref::<MpdError>() {
+                            // idle again without the subsystems this version of mpd doesn't know
+                            Some(e)
+                                if Subsystem::rejected(e)
+                                    .is_some_and(|x| subsystems.contains(&x)) =>
+                            {
+                                subsystems.retain(|&x| Some(x) != Subsystem::rejected(e));
+                                continue;
+                            }
+                            // the connection still works, e.g. the password lacks the read permission
+                            Some(_) => {
+                                backoff.fail();
+                                backoff.wait().await;
+                                continue;
+                            }
+                            None => {
+                                // let the main thread notice the disconnection
+                                updates1.fetch_or(
+                                    UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME,
+                                    Ordering::Relaxed,
+                                );
+                                t1.unpark();
+
+                                backoff.fail();
+                                idle_cl = loop {
+                                    backoff.wait().await;
+                                    match Client::init(&idle_addr, idle_password.as_deref()).await {
+                                        Ok(cl) => break cl,
+                                        Err(_) => backoff.fail(),
+                                    }
+                                };
+                                UPDATE_ALL | RECONNECT
+                            }
+                        },
                     },
                     Ordering::Relaxed,
                 );
@@ -199,273 +369,912 @@ async fn run() -> Result<()> {
         block_on(async move {
             let mut timer = Timer::interval(update_interval);
             loop {
-                updates2.fetch_or(0b101, Ordering::Relaxed);
+                updates2.fetch_or(UPDATE_FRAME, Ordering::Relaxed);
                 t2.unpark();
                 timer.next().await;
             }
         })
     });
 
+    let bindings = mem::take(&mut cfg.bindings);
+    let key_timeout = Duration::from_secs_f32(cfg.key_timeout);
     thread::spawn(move || {
-        let mut searching = false;
-        while let Ok(ev) = event::read() {
-            cmds1.push(match ev {
-                Event::Mouse(MouseEvent {
-                    kind: MouseEventKind::ScrollUp,
-                    ..
-                }) => Command::Up,
-                Event::Mouse(MouseEvent {
-                    kind: MouseEventKind::ScrollDown,
-                    ..
-                }) => Command::Down,
-                Event::Resize(..) => {
-                    updates3.fetch_or(0b001, Ordering::Relaxed);
+        let mut pending = Vec::new();
+        let mut count = None;
+        loop {
+            // forget the incomplete binding if the next key takes too long
+            if (!pending.is_empty() || count.is_some()) && !event::poll(key_timeout).unwrap_or(true)
+            {
+                if text_input1.load(Ordering::Relaxed) {
+                    for c in typed_chars(&pending) {
+                        cmds1.push((Command::InputSearch(c), None));
+                    }
+                }
+                pending.clear();
+                count = None;
+                cmds1.push((Command::PendingKeys(String::new()), None));
+                t3.unpark();
+                continue;
+            }
+            let Ok(ev) = event::read() else {
+                break;
+            };
+            if let Event::Resize(..) = ev {
+                updates3.fetch_or(UPDATE_FRAME, Ordering::Relaxed);
+                t3.unpark();
+                continue;
+            }
+            let Some(chord) = Chord::from_event(&ev) else {
+                continue;
+            };
+            let searching = text_input1.load(Ordering::Relaxed);
+            let bindings = if searching {
+                &bindings.search
+            } else {
+                &bindings.normal
+            };
+
+            // digits that no binding starts with are counts in normal mode
+            if let Chord::Key(KeyCode::Char(c @ '0' ..= '9'), KeyModifiers::NONE) = chord {
+                if !searching
+                    && pending.is_empty()
+                    && (c != '0' || count.is_some())
+                    && !bindings.keys().any(|keys| keys[0] == chord)
+                {
+                    let digit = c as usize - '0' as usize;
+                    count = Some(
+                        count
+                            .unwrap_or(0usize)
+                            .saturating_mul(10)
+                            .saturating_add(digit),
+                    );
+                    cmds1.push((Command::PendingKeys(pending_keys(count, &pending)), None));
                     t3.unpark();
                     continue;
                 }
-                Event::Key(KeyEvent {
-                    code, modifiers, ..
-                }) => match code {
-                    KeyCode::Char('q') if modifiers.contains(KeyModifiers::CONTROL) => {
-                        Command::Quit
-                    }
-                    KeyCode::Char('u') if modifiers.contains(KeyModifiers::CONTROL) => {
-                        if searching {
-                            Command::ClearSearch
-                        } else {
-                            Command::JumpUp
-                        }
+            }
+
+            pending.push(chord);
+            let cmd = match bindings.get(&pending) {
+                Some(Command::Click) => match ev {
+                    Event::Mouse(MouseEvent { column, row, .. }) => {
+                        Some(Command::ClickAt(column, row))
                     }
-                    KeyCode::Left => Command::SeekBackwards,
-                    KeyCode::Right => Command::SeekForwards,
-                    KeyCode::Down => Command::Down,
-                    KeyCode::Up => Command::Up,
-                    KeyCode::PageDown => Command::JumpDown,
-                    KeyCode::PageUp => Command::JumpUp,
-                    KeyCode::Enter if searching => {
-                        searching = false;
-                        Command::Searching(false)
-                    }
-                    KeyCode::Enter => Command::Play,
-                    KeyCode::Backspace if searching => Command::BackspaceSearch,
-                    KeyCode::Esc => {
-                        searching = false;
-                        Command::QuitSearch
-                    }
-                    KeyCode::Char(c) if searching => Command::InputSearch(c),
-                    KeyCode::Char(c) => match c {
-                        'q' => Command::Quit,
-                        'r' => Command::ToggleRepeat,
-                        'R' => Command::ToggleRandom,
-                        's' => Command::ToggleSingle,
-                        'S' => Command::ToggleOneshot,
-                        'c' => Command::ToggleConsume,
-                        'p' => Command::TogglePause,
-                        ';' => Command::Stop,
-                        'h' => Command::SeekBackwards,
-                        'l' => Command::SeekForwards,
-                        'H' => Command::Previous,
-                        'L' => Command::Next,
-                        ' ' => Command::Reselect,
-                        'j' => Command::Down,
-                        'k' => Command::Up,
-                        'J' => Command::JumpDown,
-                        'd' if modifiers.contains(KeyModifiers::CONTROL) => Command::JumpDown,
-                        'K' => Command::JumpUp,
-                        'g' => Command::GotoTop,
-                        'G' => Command::GotoBottom,
-                        '/' => {
-                            searching = true;
-                            Command::Searching(true)
-                        }
-                        _ => continue,
-                    },
-                    _ => continue,
+                    _ => None,
                 },
-                _ => continue,
-            });
+                Some(cmd) => Some(cmd.clone()),
+                None if bindings.keys().any(|keys| keys.starts_with(&pending)) => {
+                    cmds1.push((Command::PendingKeys(pending_keys(count, &pending)), None));
+                    t3.unpark();
+                    continue;
+                }
+                // unbound characters are typed into the query, including abandoned sequences
+                None if searching => {
+                    let mut chars = typed_chars(&pending);
+                    let last = chars.pop();
+                    for c in chars {
+                        cmds1.push((Command::InputSearch(c), None));
+                    }
+                    last.map(Command::InputSearch)
+                }
+                None => None,
+            };
+
+            if pending.len() > 1 || count.is_some() {
+                cmds1.push((Command::PendingKeys(String::new()), None));
+            }
+            pending.clear();
+            let count = count.take();
+            if let Some(cmd) = cmd {
+                if let Some(x) = cmd.text_input() {
+                    text_input1.store(x, Ordering::Relaxed);
+                }
+                let count = count.filter(|_| cmd.countable());
+                cmds1.push((cmd, count));
+            }
             t3.unpark();
         }
     });
 
+    let mut queue_version = Some(s.status.version);
+    let mut unmuted_volume = None;
+    let mut backoff = Backoff::new();
     loop {
-        let updates = if let Some(cmd) = cmds.pop() {
+        // reconnect if the idle connection came back or the last attempt was long enough ago
+        let reconnect = updates.fetch_and(!RECONNECT, Ordering::SeqCst) & RECONNECT != 0;
+        if !s.connected && (reconnect || backoff.ready()) {
+            let init = future::or(Client::init(addr, password.as_deref()), async {
+                Timer::after(RECONNECT_TIMEOUT).await;
+                Err(anyhow!("Timed out connecting to mpd"))
+            });
+            match init.await {
+                Ok(new_cl) => {
+                    cl = new_cl;
+                    s.connected = true;
+                    // mpd may have been restarted, which resets the queue version
+                    queue_version = None;
+                    s.info(String::from("Reconnected to mpd"));
+                    backoff.reset();
+                    updates.fetch_or(UPDATE_ALL, Ordering::SeqCst);
+                }
+                Err(_) => backoff.fail(),
+            }
+        }
+
+        let updates = if let Some((cmd, count)) = cmds.pop() {
+            if cmd.queue_only() && s.focus != View::Queue {
+                continue;
+            }
             (match cmd {
                 Command::Quit => return Ok(()),
-                Command::ToggleRepeat => {
-                    cl.command(if s.status.repeat {
+                Command::ToggleRepeat => cl
+                    .command(if s.status.repeat {
                         b"repeat 0"
                     } else {
                         b"repeat 1"
                     })
                     .await
-                    .context("Failed to toggle repeat")?;
-                    0b101
-                }
-                Command::ToggleRandom => {
-                    cl.command(if s.status.random {
+                    .context("Failed to toggle repeat")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::ToggleRandom => cl
+                    .command(if s.status.random {
                         b"random 0"
                     } else {
                         b"random 1"
                     })
                     .await
-                    .context("Failed to toggle random")?;
-                    0b101
-                }
-                Command::ToggleSingle => {
-                    cl.command(if s.status.single == Some(true) {
+                    .context("Failed to toggle random")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::ToggleSingle => cl
+                    .command(if s.status.single == Some(true) {
                         b"single 0"
                     } else {
                         b"single 1"
                     })
                     .await
-                    .context("Failed to toggle single")?;
-                    0b101
-                }
-                Command::ToggleOneshot => {
-                    cl.command(s.status.single.map_or(b"single 0", |_| b"single oneshot"))
-                        .await
-                        .context("Failed to toggle oneshot")?;
-                    0b101
-                }
-                Command::ToggleConsume => {
-                    cl.command(if s.status.consume {
+                    .context("Failed to toggle single")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::ToggleOneshot => cl
+                    .command(s.status.single.map_or(b"single 0", |_| b"single oneshot"))
+                    .await
+                    .context("Failed to toggle oneshot")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::ToggleConsume => cl
+                    .command(if s.status.consume {
                         b"consume 0"
                     } else {
                         b"consume 1"
                     })
                     .await
-                    .context("Failed to toggle consume")?;
-                    0b101
-                }
-                Command::TogglePause => {
-                    cl.command(match s.status.state {
+                    .context("Failed to toggle consume")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::TogglePause => cl
+                    .command(match s.status.state {
                         PlayerState::Play => b"pause",
                         PlayerState::Pause => b"play",
                         _ => continue,
                     })
                     .await
-                    .context("Failed to toggle pause")?;
-                    0b101
+                    .context("Failed to toggle pause")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::Stop => cl
+                    .command(b"stop")
+                    .await
+                    .context("Failed to stop playing")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::SeekBackwards => cl
+                    .command(
+                        format!(
+                            "seekcur -{}",
+                            settings.seek_secs * count.unwrap_or(1) as f32
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .context("Failed to seek backwards")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::SeekForwards => cl
+                    .command(
+                        format!(
+                            "seekcur +{}",
+                            settings.seek_secs * count.unwrap_or(1) as f32
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .context("Failed to seek forwards")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::VolumeUp => {
+                    if s.status.volume.is_some() {
+                        cl.change_volume(settings.volume_step.into())
+                            .await
+                            .context("Failed to increase volume")
+                            .map(|()| UPDATE_STATUS | UPDATE_FRAME)
+                    } else {
+                        Ok(0)
+                    }
+                }
+                Command::VolumeDown => {
+                    if s.status.volume.is_some() {
+                        cl.change_volume(-i16::from(settings.volume_step))
+                            .await
+                            .context("Failed to decrease volume")
+                            .map(|()| UPDATE_STATUS | UPDATE_FRAME)
+                    } else {
+                        Ok(0)
+                    }
                 }
-                Command::Stop => {
-                    cl.command(b"stop")
+                Command::ToggleMute => match s.status.volume {
+                    Some(0) => cl
+                        .set_volume(unmuted_volume.take().unwrap_or(settings.volume_step))
                         .await
-                        .context("Failed to stop playing")?;
-                    0b101
+                        .context("Failed to unmute")
+                        .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                    Some(volume) => {
+                        unmuted_volume = Some(volume);
+                        cl.set_volume(0)
+                            .await
+                            .context("Failed to mute")
+                            .map(|()| UPDATE_STATUS | UPDATE_FRAME)
+                    }
+                    None => Ok(0),
+                },
+                Command::Delete if s.focus == View::Playlists => match s.playlists.list.get() {
+                    // deleting a playlist can't be undone
+                    Some(Entry::Playlist(name)) => {
+                        s.prompt = Some(Prompt::new(PromptKind::DeletePlaylist(name.clone())));
+                        text_input.store(true, Ordering::Relaxed);
+                        Ok(UPDATE_FRAME)
+                    }
+                    Some(Entry::Song(_)) => {
+                        let cmd = format!(
+                            "playlistdelete {} {}",
+                            quote(&s.playlists.path),
+                            s.playlists.list.selected,
+                        );
+                        cl.command(cmd.as_bytes())
+                            .await
+                            .context("Failed to delete the song from the playlist")
+                            .map(|()| UPDATE_PLAYLISTS | UPDATE_FRAME)
+                    }
+                    _ => Ok(0),
+                },
+                Command::Delete if s.focus != View::Queue => Ok(0),
+                Command::Delete => {
+                    let targets = match count {
+                        // delete the selected song and the ones after it
+                        Some(count) if !s.has_marks() => (s.selected
+                            .. s.selected.saturating_add(count))
+                            .map_while(|row| s.row_pos(row))
+                            .collect(),
+                        _ => s.targets(),
+                    };
+                    let cmds: Vec<_> = targets
+                        .into_iter()
+                        .filter_map(|pos| Some(format!("deleteid {}", s.queue[pos].id?)))
+                        .collect();
+                    s.clear_marks();
+                    cl.command_list(&cmds)
+                        .await
+                        .context("Failed to delete songs")
+                        .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
                 }
-                Command::SeekBackwards => {
-                    cl.command(seek_backwards)
+                Command::MoveUp => {
+                    let targets = s.targets();
+                    let cmds: Vec<_> = if s.has_marks() {
+                        // move every marked song up by one, as long as the first one can move
+                        if targets.first().is_some_and(|&pos| pos > 0) {
+                            targets
+                                .into_iter()
+                                .filter_map(|pos| {
+                                    Some(format!("moveid {} {}", s.queue[pos].id?, pos - 1))
+                                })
+                                .collect()
+                        } else {
+                            Vec::new()
+                        }
+                    } else if let (Some(id), Some(to)) = (
+                        s.selected_id(),
+                        s.selected.checked_sub(1).and_then(|row| s.row_pos(row)),
+                    ) {
+                        vec![format!("moveid {id} {to}")]
+                    } else {
+                        Vec::new()
+                    };
+                    cl.command_list(&cmds)
                         .await
-                        .context("Failed to seek backwards")?;
-                    0b101
+                        .context("Failed to move songs up")
+                        .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
                 }
-                Command::SeekForwards => {
-                    cl.command(seek_forwards)
+                Command::MoveDown => {
+                    let targets = s.targets();
+                    let cmds: Vec<_> = if s.has_marks() {
+                        // move every marked song down by one, as long as the last one can move
+                        if targets.last().is_some_and(|&pos| pos + 1 < s.queue.len()) {
+                            targets
+                                .into_iter()
+                                .rev()
+                                .filter_map(|pos| {
+                                    Some(format!("moveid {} {}", s.queue[pos].id?, pos + 1))
+                                })
+                                .collect()
+                        } else {
+                            Vec::new()
+                        }
+                    } else if let (Some(id), Some(to)) =
+                        (s.selected_id(), s.row_pos(s.selected + 1))
+                    {
+                        vec![format!("moveid {id} {to}")]
+                    } else {
+                        Vec::new()
+                    };
+                    cl.command_list(&cmds)
                         .await
-                        .context("Failed to seek forwards")?;
-                    0b101
+                        .context("Failed to move songs down")
+                        .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
                 }
-                Command::Previous => {
-                    cl.command(b"previous")
+                Command::PriorityUp | Command::PriorityDown => {
+                    let up = matches!(cmd, Command::PriorityUp);
+                    let cmds: Vec<_> = s
+                        .targets()
+                        .into_iter()
+                        .filter_map(|pos| {
+                            let track = &s.queue[pos];
+                            let priority = if up {
+                                track.priority.saturating_add(1)
+                            } else {
+                                track.priority.saturating_sub(1)
+                            };
+                            Some(format!("prioid {priority} {}", track.id?))
+                        })
+                        .collect();
+                    cl.command_list(&cmds)
                         .await
-                        .context("Failed to play previous song")?;
-                    0b101
+                        .context("Failed to change the priority")
+                        .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
                 }
-                Command::Next => {
-                    cl.command(b"next")
+                Command::ToggleMark => {
+                    s.toggle_mark();
+                    if s.selected + 1 < s.len() {
+                        s.select(s.selected + 1);
+                    }
+                    Ok(UPDATE_FRAME)
+                }
+                Command::Visual => {
+                    s.toggle_visual();
+                    Ok(UPDATE_FRAME)
+                }
+                Command::ClearMarks => {
+                    s.clear_marks();
+                    Ok(UPDATE_FRAME)
+                }
+                Command::Clear => cl
+                    .command(b"clear")
+                    .await
+                    .context("Failed to clear the queue")
+                    .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME),
+                Command::Shuffle => {
+                    let range = if s.has_marks() {
+                        // shuffle the marked songs, as long as they are next to each other
+                        let targets = s.targets();
+                        match (targets.first(), targets.last()) {
+                            (Some(&start), Some(&end)) if end - start + 1 == targets.len() => {
+                                Some((start, end + 1))
+                            }
+                            (Some(_), _) => {
+                                s.error(String::from("Only consecutive songs can be shuffled"));
+                                None
+                            }
+                            _ => None,
+                        }
+                    } else {
+                        // only shuffle the songs after the current one
+                        let start = s.status.song.as_ref().map_or(0, |song| song.pos + 1);
+                        Some((start, s.queue.len()))
+                    };
+                    match range {
+                        Some((start, end)) if start + 1 < end => {
+                            s.clear_marks();
+                            cl.shuffle(start, end)
+                                .await
+                                .context("Failed to shuffle the queue")
+                                .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
+                        }
+                        _ => Ok(UPDATE_FRAME),
+                    }
+                }
+                Command::Crop => {
+                    if let Some(song) = &s.status.song {
+                        cl.crop(song.pos, s.queue.len())
+                            .await
+                            .context("Failed to crop the queue")
+                            .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
+                    } else {
+                        Ok(0)
+                    }
+                }
+                Command::Click => Ok(0),
+                Command::ClickAt(x, y) => {
+                    if let Some(secs) = s.progress_position(x, y) {
+                        cl.seek(secs)
+                            .await
+                            .context("Failed to seek")
+                            .map(|()| UPDATE_STATUS | UPDATE_FRAME)
+                    } else {
+                        Ok(0)
+                    }
+                }
+                Command::Previous => cl
+                    .command(b"previous")
+                    .await
+                    .context("Failed to play previous song")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::Next => cl
+                    .command(b"next")
+                    .await
+                    .context("Failed to play next song")
+                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                Command::Play if s.focus == View::Browser => match s.browser.list.get() {
+                    Some(Entry::Directory(path)) => {
+                        let path = path.clone();
+                        cl.ls(&path).await.map(|entries| {
+                            s.browser.enter(path, entries);
+                            UPDATE_FRAME
+                        })
+                    }
+                    Some(Entry::Song(track)) => cl
+                        .add_and_play(&track.file)
+                        .await
+                        .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME),
+                    Some(Entry::Playlist(name)) => cl
+                        .load(name, false)
+                        .await
+                        .context("Failed to load the selected playlist")
+                        .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME),
+                    None => Ok(0),
+                },
+                Command::Play if s.focus == View::Playlists => match s.playlists.list.get() {
+                    Some(Entry::Playlist(name)) => {
+                        let name = name.clone();
+                        cl.playlist(&name).await.map(|entries| {
+                            s.playlists.enter(name, entries);
+                            UPDATE_FRAME
+                        })
+                    }
+                    Some(Entry::Song(track)) => cl
+                        .add_and_play(&track.file)
                         .await
-                        .context("Failed to play next song")?;
-                    0b101
+                        .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME),
+                    _ => Ok(0),
+                },
+                Command::Play | Command::ToggleOutput if s.focus == View::Outputs => {
+                    if let Some(output) = s.outputs.get() {
+                        cl.command(format!("toggleoutput {}", output.id).as_bytes())
+                            .await
+                            .with_context(|| format!("Failed to toggle output {}", output.name))
+                            .map(|()| UPDATE_OUTPUTS | UPDATE_FRAME)
+                    } else {
+                        Ok(0)
+                    }
                 }
-                Command::Play => {
-                    cl.play(if s.query.is_empty() {
-                        if s.selected < s.queue.len() {
-                            s.selected
-                        } else {
-                            continue;
+                Command::SwitchOutput if s.focus == View::Outputs => {
+                    if let Some(output) = s.outputs.get() {
+                        let mut cmds = vec![format!("enableoutput {}", output.id)];
+                        for other in &s.outputs.items {
+                            if other.id != output.id && other.enabled {
+                                cmds.push(format!("disableoutput {}", other.id));
+                            }
                         }
-                    } else if let Some(&x) = s.filtered.get(s.selected) {
-                        x
+                        cl.command_list(&cmds)
+                            .await
+                            .with_context(|| format!("Failed to switch to output {}", output.name))
+                            .map(|()| UPDATE_OUTPUTS | UPDATE_FRAME)
                     } else {
+                        Ok(0)
+                    }
+                }
+                Command::ToggleOutput | Command::SwitchOutput => Ok(0),
+                Command::Play if s.focus == View::Search => {
+                    if let Some(track) = s.search.list.get() {
+                        cl.add_and_play(&track.file)
+                            .await
+                            .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
+                    } else {
+                        Ok(0)
+                    }
+                }
+                Command::Play if s.focus == View::Library => {
+                    let library = &mut s.library;
+                    if library.active < library.groups.len() {
+                        if library.groups[library.active].get().is_some() {
+                            library.active += 1;
+                        }
+                        Ok(UPDATE_FRAME)
+                    } else if let Some(track) = library.tracks.get() {
+                        cl.add_and_play(&track.file)
+                            .await
+                            .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
+                    } else {
+                        Ok(0)
+                    }
+                }
+                Command::Play => {
+                    let Some(pos) = s.selected_pos() else {
                         continue;
-                    })
-                    .await
-                    .context("Failed to play the selected song")?;
+                    };
+                    let res = cl
+                        .play(pos)
+                        .await
+                        .context("Failed to play the selected song");
                     if clear_query_on_play {
                         s.quit_search();
                     }
-                    0b101
+                    res.map(|()| UPDATE_STATUS | UPDATE_FRAME)
                 }
                 Command::Reselect => {
-                    s.reselect();
-                    0b001
+                    s.focused().reselect();
+                    Ok(UPDATE_FRAME)
                 }
-                Command::Down => {
-                    let len = s.len();
-                    if s.selected >= len {
-                        s.reselect();
-                    } else if s.selected == len - 1 {
-                        if cycle {
-                            s.select(0);
-                        }
+                Command::FocusNext | Command::FocusPrevious
+                    if matches!(
+                        s.prompt,
+                        Some(Prompt {
+                            kind: PromptKind::CommandLine(_),
+                            ..
+                        })
+                    ) =>
+                {
+                    let Some(Prompt {
+                        kind: PromptKind::CommandLine(editor),
+                        input,
+                    }) = &mut s.prompt
+                    else {
+                        continue;
+                    };
+                    let forward = matches!(cmd, Command::FocusNext);
+                    if editor.cycle(input, forward) {
+                        Ok(UPDATE_FRAME)
                     } else {
-                        s.select(s.selected + 1);
+                        let (start, word, target) = cmdline::target(input);
+                        let (request, key) = match &target {
+                            Target::Command => (Some(String::from("commands")), "command"),
+                            Target::Playlist => (Some(String::from("listplaylists")), "playlist"),
+                            Target::TagType => (Some(String::from("tagtypes")), "tagtype"),
+                            Target::TagValue(tag) => {
+                                (Some(format!("list {}", quote(tag))), tag.as_str())
+                            }
+                            Target::Setting | Target::Nothing => (None, ""),
+                        };
+                        let pairs = match request {
+                            Some(request) => cl
+                                .command_pairs(request.as_bytes())
+                                .await
+                                .context("Failed to complete the command"),
+                            None => Ok(Vec::new()),
+                        };
+                        pairs.map(|pairs| {
+                            let mut candidates: Vec<_> = pairs
+                                .into_iter()
+                                .filter_map(|(k, v)| k.eq_ignore_ascii_case(key).then_some(v))
+                                .collect();
+                            match target {
+                                Target::Command => candidates.extend(COMMANDS.map(String::from)),
+                                Target::Setting => candidates.extend(SETTINGS.map(String::from)),
+                                _ => {}
+                            }
+                            editor.complete(input, start, &word, candidates, forward);
+                            UPDATE_FRAME
+                        })
                     }
-                    0b001
                 }
-                Command::Up => {
-                    let len = s.len();
-                    if s.selected >= len {
-                        s.reselect();
-                    } else if s.selected == 0 {
-                        if cycle {
-                            s.select(len - 1);
+                Command::FocusNext | Command::FocusPrevious => {
+                    if let Some(i) = views.iter().position(|&view| view == s.focus) {
+                        let len = views.len();
+                        s.focus = views[if matches!(cmd, Command::FocusNext) {
+                            (i + 1) % len
+                        } else {
+                            (i + len - 1) % len
+                        }];
+                    }
+                    Ok(UPDATE_FRAME)
+                }
+                Command::Back => match s.focus {
+                    View::Browser => {
+                        if let Some(parent) = s.browser.parent() {
+                            cl.ls(&parent).await.map(|entries| {
+                                s.browser.leave(parent, entries);
+                                UPDATE_FRAME
+                            })
+                        } else {
+                            Ok(0)
+                        }
+                    }
+                    View::Playlists => {
+                        if let Some(parent) = s.playlists.parent() {
+                            cl.playlists().await.map(|entries| {
+                                s.playlists.leave(parent, entries);
+                                UPDATE_FRAME
+                            })
+                        } else {
+                            Ok(0)
                         }
+                    }
+                    View::Library if s.library.active > 0 => {
+                        s.library.active -= 1;
+                        Ok(UPDATE_FRAME)
+                    }
+                    _ => Ok(0),
+                },
+                Command::Add | Command::Insert => {
+                    let insert = matches!(cmd, Command::Insert);
+                    match (s.focus, s.selected_entry()) {
+                        (_, Some(Entry::Playlist(name))) => cl
+                            .load(name, insert)
+                            .await
+                            .context("Failed to add the selected playlist")
+                            .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME),
+                        (_, Some(entry)) => cl
+                            .add(entry.uri(), insert)
+                            .await
+                            .context("Failed to add the selected entry")
+                            .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME),
+                        (View::Library, _) => {
+                            let library = &s.library;
+                            if library.active < library.groups.len() {
+                                if let Some(filter) = library.filter(library.active + 1) {
+                                    cl.find_add(&filter, insert)
+                                        .await
+                                        .context("Failed to add the selected songs")
+                                        .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
+                                } else {
+                                    Ok(0)
+                                }
+                            } else if let Some(track) = library.tracks.get() {
+                                cl.add(&track.file, insert)
+                                    .await
+                                    .context("Failed to add the selected song")
+                                    .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
+                            } else {
+                                Ok(0)
+                            }
+                        }
+                        (View::Search, _) => {
+                            if let Some(track) = s.search.list.get() {
+                                cl.add(&track.file, insert)
+                                    .await
+                                    .context("Failed to add the selected song")
+                                    .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
+                            } else {
+                                Ok(0)
+                            }
+                        }
+                        _ => Ok(0),
+                    }
+                }
+                Command::Replace => {
+                    let add = match s.focus {
+                        View::Browser | View::Playlists => match s.selected_entry() {
+                            Some(Entry::Playlist(name)) => Some(format!("load {}", quote(name))),
+                            Some(entry) => Some(format!("add {}", quote(entry.uri()))),
+                            None => None,
+                        },
+                        View::Library => {
+                            let library = &s.library;
+                            if library.active < library.groups.len() {
+                                library.filter(library.active + 1).map(|filter| {
+                                    format!("findadd {}", quote(&filter_expr(&filter)))
+                                })
+                            } else {
+                                library
+                                    .tracks
+                                    .get()
+                                    .map(|track| format!("add {}", quote(&track.file)))
+                            }
+                        }
+                        View::Search => s
+                            .search
+                            .list
+                            .get()
+                            .map(|track| format!("add {}", quote(&track.file))),
+                        View::Queue | View::Outputs => None,
+                    };
+                    if let Some(add) = add {
+                        cl.command_list(&["clear".into(), add, "play".into()])
+                            .await
+                            .context("Failed to replace the queue")
+                            .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
                     } else {
-                        s.select(s.selected - 1);
+                        Ok(0)
                     }
-                    0b001
                 }
-                Command::JumpDown => {
-                    let len = s.len();
-                    if s.selected >= len {
-                        s.reselect();
-                    } else if cycle {
-                        s.select((s.selected + jump_lines) % len);
+                Command::AddAll | Command::ReplaceAll => {
+                    if let (View::Search, Some(expr)) = (s.focus, &s.search.expr) {
+                        let add = format!("searchadd {}", quote(expr));
+                        if matches!(cmd, Command::AddAll) {
+                            cl.command(add.as_bytes())
+                                .await
+                                .context("Failed to add the search results")
+                                .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
+                        } else {
+                            cl.command_list(&["clear".into(), add, "play".into()])
+                                .await
+                                .context("Failed to replace the queue")
+                                .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME)
+                        }
                     } else {
-                        s.select(min(s.selected + jump_lines, len - 1));
+                        Ok(0)
+                    }
+                }
+                Command::SaveQueue => {
+                    s.prompt = Some(Prompt::new(PromptKind::SaveQueue));
+                    Ok(UPDATE_FRAME)
+                }
+                Command::AddToPlaylist => {
+                    let uris = match s.focus {
+                        View::Queue => Ok(s
+                            .targets()
+                            .into_iter()
+                            .map(|pos| s.queue[pos].file.clone())
+                            .collect()),
+                        View::Browser | View::Playlists => Ok(match s.selected_entry() {
+                            Some(Entry::Playlist(_)) | None => Vec::new(),
+                            Some(entry) => vec![entry.uri().into()],
+                        }),
+                        View::Library => {
+                            let library = &s.library;
+                            if library.active < library.groups.len() {
+                                match library.filter(library.active + 1) {
+                                    Some(filter) => cl.find(&filter).await.map(|tracks| {
+                                        tracks.into_iter().map(|track| track.file).collect()
+                                    }),
+                                    None => Ok(Vec::new()),
+                                }
+                            } else {
+                                Ok(library
+                                    .tracks
+                                    .get()
+                                    .map(|track| track.file.clone())
+                                    .into_iter()
+                                    .collect())
+                            }
+                        }
+                        View::Search => Ok(s
+                            .search
+                            .list
+                            .get()
+                            .map(|track| track.file.clone())
+                            .into_iter()
+                            .collect()),
+                        View::Outputs => Ok(Vec::new()),
                     };
-                    0b001
+
+                    match uris {
+                        Ok(uris) if !uris.is_empty() => {
+                            s.prompt = Some(Prompt::new(PromptKind::AddToPlaylist(uris)));
+                            Ok(UPDATE_FRAME)
+                        }
+                        res => {
+                            // nothing to add, the input thread already switched to typing
+                            text_input.store(false, Ordering::Relaxed);
+                            res.map(|_| 0)
+                        }
+                    }
                 }
-                Command::JumpUp => {
-                    let len = s.len();
-                    if s.selected >= len {
-                        s.reselect();
-                    } else if cycle {
-                        while s.selected < jump_lines {
-                            s.selected += len;
+                Command::RenamePlaylist => {
+                    let name = match (s.focus, s.playlists.list.get()) {
+                        (View::Playlists, Some(Entry::Playlist(name))) => Some(name.clone()),
+                        (View::Playlists, _) if !s.playlists.path.is_empty() => {
+                            Some(s.playlists.path.clone())
                         }
-                        s.selected -= jump_lines;
-                        s.liststate.select(Some(s.selected));
-                    } else if s.selected < jump_lines {
-                        s.select(0);
-                    } else {
-                        s.select(s.selected - jump_lines);
+                        _ => None,
                     };
-                    0b001
+                    if let Some(name) = name {
+                        s.prompt = Some(Prompt::new(PromptKind::RenamePlaylist(name)));
+                    } else {
+                        // the input thread already switched to typing
+                        text_input.store(false, Ordering::Relaxed);
+                    }
+                    Ok(UPDATE_FRAME)
+                }
+                Command::Cmdline => {
+                    s.prompt = Some(Prompt::new(PromptKind::CommandLine(Editor::default())));
+                    Ok(UPDATE_FRAME)
+                }
+                Command::Down | Command::Up
+                    if matches!(
+                        s.prompt,
+                        Some(Prompt {
+                            kind: PromptKind::CommandLine(_),
+                            ..
+                        })
+                    ) =>
+                {
+                    if let Some(Prompt {
+                        kind: PromptKind::CommandLine(editor),
+                        input,
+                    }) = &mut s.prompt
+                    {
+                        if matches!(cmd, Command::Up) {
+                            editor.history_up(input, &s.history);
+                        } else {
+                            editor.history_down(input, &s.history);
+                        }
+                    }
+                    Ok(UPDATE_FRAME)
+                }
+                Command::Down => {
+                    match count {
+                        Some(count) => s.focused().jump_down(count, settings.cycle),
+                        None => s.focused().down(settings.cycle),
+                    }
+                    Ok(UPDATE_FRAME)
+                }
+                Command::Up => {
+                    match count {
+                        Some(count) => s.focused().jump_up(count, settings.cycle),
+                        None => s.focused().up(settings.cycle),
+                    }
+                    Ok(UPDATE_FRAME)
+                }
+                Command::JumpDown => {
+                    s.focused().jump_down(
+                        settings.jump_lines.saturating_mul(count.unwrap_or(1)),
+                        settings.cycle,
+                    );
+                    Ok(UPDATE_FRAME)
+                }
+                Command::JumpUp => {
+                    s.focused().jump_up(
+                        settings.jump_lines.saturating_mul(count.unwrap_or(1)),
+                        settings.cycle,
+                    );
+                    Ok(UPDATE_FRAME)
+                }
+                // with a count, both go to that row like in vim
+                Command::GotoTop | Command::GotoBottom if count.is_some() => {
+                    s.focused().goto(count.unwrap_or(1).saturating_sub(1));
+                    Ok(UPDATE_FRAME)
                 }
                 Command::GotoTop => {
-                    s.select(0);
-                    0b001
+                    s.focused().goto_top();
+                    Ok(UPDATE_FRAME)
                 }
                 Command::GotoBottom => {
-                    let len = s.len();
-                    if len == 0 {
-                        continue;
+                    s.focused().goto_bottom();
+                    Ok(UPDATE_FRAME)
+                }
+                Command::Mpd(cmds) => match cmds
+                    .iter()
+                    .map(|cmd| {
+                        let cmd = expand(cmd, |name| s.placeholder(name))?;
+                        check_command(&cmd)?;
+                        Ok(cmd)
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(cmds) => cl
+                        .command_list(&cmds)
+                        .await
+                        .context("Failed to run mpd commands")
+                        .map(|()| UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME),
+                    Err(e) => {
+                        s.error(e);
+                        Ok(UPDATE_FRAME)
+                    }
+                },
+                Command::PendingKeys(keys) => {
+                    s.pending_keys = keys;
+                    Ok(UPDATE_FRAME)
+                }
+                Command::InputSearch(c) if s.prompt.is_some() => {
+                    if let Some(prompt) = &mut s.prompt {
+                        prompt.input.push(c);
                     }
-                    s.select(len - 1);
-                    0b001
+                    Ok(UPDATE_FRAME)
+                }
+                Command::InputSearch(c) if s.search.editing => {
+                    s.search.query.push(c);
+                    Ok(UPDATE_FRAME)
                 }
                 Command::InputSearch(c) => {
                     let empty = s.query.is_empty();
@@ -476,7 +1285,17 @@ async fn run() -> Result<()> {
                         let query = lower_lay_string(&s.query);
                         s.filtered.retain(|&i| queue_strings[i].contains(&query));
                     }
-                    0b001
+                    Ok(UPDATE_FRAME)
+                }
+                Command::BackspaceSearch if s.prompt.is_some() => {
+                    if let Some(prompt) = &mut s.prompt {
+                        prompt.input.pop();
+                    }
+                    Ok(UPDATE_FRAME)
+                }
+                Command::BackspaceSearch if s.search.editing => {
+                    s.search.query.pop();
+                    Ok(UPDATE_FRAME)
                 }
                 Command::BackspaceSearch => {
                     let c = s.query.pop();
@@ -485,53 +1304,345 @@ async fn run() -> Result<()> {
                     } else if c.is_some() {
                         s.reselect();
                     }
-                    0b001
+                    Ok(UPDATE_FRAME)
+                }
+                Command::ClearSearch if s.prompt.is_some() => {
+                    if let Some(prompt) = &mut s.prompt {
+                        prompt.input.clear();
+                    }
+                    Ok(UPDATE_FRAME)
+                }
+                Command::ClearSearch if s.search.editing => {
+                    s.search.query.clear();
+                    Ok(UPDATE_FRAME)
                 }
                 Command::ClearSearch => {
                     if !s.query.is_empty() {
                         s.query.clear();
                         s.reselect();
                     }
-                    0b001
+                    Ok(UPDATE_FRAME)
+                }
+                Command::QuitSearch if s.prompt.is_some() => {
+                    s.prompt = None;
+                    Ok(UPDATE_FRAME)
                 }
                 Command::QuitSearch => {
                     s.quit_search();
-                    0b001
+                    Ok(UPDATE_FRAME)
+                }
+                Command::Searching(false) if s.prompt.is_some() => {
+                    let Some(Prompt { kind, input }) = s.prompt.take() else {
+                        continue;
+                    };
+                    let name = input.trim();
+                    match kind {
+                        _ if name.is_empty() => Ok(UPDATE_FRAME),
+                        PromptKind::SaveQueue => cl
+                            .command(format!("save {}", quote(name)).as_bytes())
+                            .await
+                            .context("Failed to save the queue")
+                            .map(|()| {
+                                s.info(format!("Saved the queue as {name}"));
+                                UPDATE_PLAYLISTS | UPDATE_FRAME
+                            }),
+                        PromptKind::AddToPlaylist(uris) => {
+                            let cmds: Vec<_> = uris
+                                .iter()
+                                .map(|uri| format!("playlistadd {} {}", quote(name), quote(uri)))
+                                .collect();
+                            cl.command_list(&cmds)
+                                .await
+                                .context("Failed to add to the playlist")
+                                .map(|()| {
+                                    s.info(format!("Added {} songs to {name}", cmds.len()));
+                                    UPDATE_PLAYLISTS | UPDATE_FRAME
+                                })
+                        }
+                        PromptKind::RenamePlaylist(old) => cl
+                            .command(format!("rename {} {}", quote(&old), quote(name)).as_bytes())
+                            .await
+                            .context("Failed to rename the playlist")
+                            .map(|()| UPDATE_PLAYLISTS | UPDATE_FRAME),
+                        PromptKind::DeletePlaylist(playlist) => {
+                            if matches!(&*name.to_lowercase(), "y" | "yes") {
+                                cl.command(format!("rm {}", quote(&playlist)).as_bytes())
+                                    .await
+                                    .context("Failed to delete the playlist")
+                                    .map(|()| {
+                                        s.info(format!("Deleted {playlist}"));
+                                        UPDATE_PLAYLISTS | UPDATE_FRAME
+                                    })
+                            } else {
+                                Ok(UPDATE_FRAME)
+                            }
+                        }
+                        PromptKind::CommandLine(_) => {
+                            if s.history.last().map(String::as_str) != Some(name) {
+                                s.history.push(name.into());
+                            }
+                            match cmdline::parse(name) {
+                                Ok(Line::Command(cmd)) => {
+                                    cmds.push((cmd, None));
+                                    Ok(UPDATE_FRAME)
+                                }
+                                Ok(Line::Seek(time)) => cl
+                                    .command(format!("seekcur {time}").as_bytes())
+                                    .await
+                                    .context("Failed to seek")
+                                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                                Ok(Line::SetVolume(volume)) => cl
+                                    .set_volume(volume)
+                                    .await
+                                    .context("Failed to set the volume")
+                                    .map(|()| UPDATE_STATUS | UPDATE_FRAME),
+                                Ok(Line::ChangeVolume(change)) => {
+                                    if s.status.volume.is_some() {
+                                        cl.change_volume(change)
+                                            .await
+                                            .context("Failed to change the volume")
+                                            .map(|()| UPDATE_STATUS | UPDATE_FRAME)
+                                    } else {
+                                        s.error(String::from("The volume can't be changed"));
+                                        Ok(UPDATE_FRAME)
+                                    }
+                                }
+                                Ok(Line::Save(name)) => cl
+                                    .command(format!("save {}", quote(&name)).as_bytes())
+                                    .await
+                                    .context("Failed to save the queue")
+                                    .map(|()| {
+                                        s.info(format!("Saved the queue as {name}"));
+                                        UPDATE_PLAYLISTS | UPDATE_FRAME
+                                    }),
+                                Ok(Line::Load(name)) => cl
+                                    .load(&name, false)
+                                    .await
+                                    .context("Failed to load the playlist")
+                                    .map(|()| {
+                                        s.info(format!("Loaded {name}"));
+                                        UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME
+                                    }),
+                                Ok(Line::Set(args)) => {
+                                    let shown: Result<Vec<_>, _> = if args.is_empty() {
+                                        Ok(SETTINGS
+                                            .iter()
+                                            .filter_map(|name| settings.get(name))
+                                            .collect())
+                                    } else {
+                                        args.iter()
+                                            .filter_map(|arg| settings.set(arg).transpose())
+                                            .collect()
+                                    };
+                                    match shown {
+                                        Ok(shown) if shown.is_empty() => {}
+                                        Ok(shown) => s.info(shown.join(", ")),
+                                        Err(e) => s.error(e),
+                                    }
+                                    Ok(UPDATE_FRAME)
+                                }
+                                Ok(Line::Mpd(line)) => cl
+                                    .command_pairs(line.as_bytes())
+                                    .await
+                                    .with_context(|| format!("Failed to run command {line}"))
+                                    .map(|pairs| {
+                                        s.info(if pairs.is_empty() {
+                                            String::from("OK")
+                                        } else {
+                                            pairs
+                                                .iter()
+                                                .map(|(key, value)| format!("{key}: {value}"))
+                                                .collect::<Vec<_>>()
+                                                .join(", ")
+                                        });
+                                        UPDATE_QUEUE | UPDATE_STATUS | UPDATE_FRAME
+                                    }),
+                                Err(e) => {
+                                    s.error(e);
+                                    Ok(UPDATE_FRAME)
+                                }
+                            }
+                        }
+                    }
+                }
+                Command::Searching(false) if s.search.editing => {
+                    s.searching = false;
+                    s.search.editing = false;
+                    if s.search.query.trim().is_empty() {
+                        Ok(UPDATE_FRAME)
+                    } else {
+                        let expr = search_expr(&s.search.query);
+                        cl.search(&expr).await.map(|tracks| {
+                            s.search.expr = Some(expr);
+                            s.search.list.items = tracks;
+                            s.search.list.select(0);
+                            if views.contains(&View::Search) {
+                                s.focus = View::Search;
+                            }
+                            UPDATE_FRAME
+                        })
+                    }
                 }
                 Command::Searching(x) => {
                     s.searching = x;
-                    0b001
+                    Ok(UPDATE_FRAME)
+                }
+                Command::SearchLibrary => {
+                    s.searching = true;
+                    s.search.editing = true;
+                    Ok(UPDATE_FRAME)
                 }
-            }) | updates.swap(0b000, Ordering::SeqCst)
+            })
+            .unwrap_or_else(|e| {
+                s.handle_error(&e);
+                UPDATE_FRAME
+            }) | updates.swap(0, Ordering::SeqCst)
         } else {
-            match updates.swap(0b000, Ordering::SeqCst) {
+            match updates.swap(0, Ordering::SeqCst) {
                 // wait for more commands or updates if neither were received
-                0b000 => {
-                    thread::park();
+                0 => {
+                    if let Some(msg) = &s.message {
+                        thread::park_timeout(msg.expires.saturating_duration_since(Instant::now()));
+                        if s.expire_message() {
+                            render(&mut term, &cfg, &mut s)?;
+                        }
+                    } else {
+                        thread::park();
+                    }
                     continue;
                 }
                 x => x,
             }
         };
 
-        // conditionally update status
-        if updates & 0b100 == 0b100 {
-            s.status = cl.status().await?;
+        // reload the levels of the library after a selection changed
+        if s.connected {
+            if let Some(level) = s.library.stale() {
+                if let Err(e) = s.library.load(&mut cl, &cfg.library, level).await {
+                    s.handle_error(&e);
+                }
+            }
+        }
+
+        // conditionally update status, and resync the playback clock every once in a while,
+        // which also keeps mpd from closing the connection after its connection_timeout
+        if s.connected
+            && (updates & UPDATE_STATUS != 0 || s.status_time.elapsed() >= STATUS_RESYNC_INTERVAL)
+        {
+            match cl.status().await {
+                Ok(status) => s.update_status(status),
+                Err(e) => s.handle_error(&e),
+            }
         }
 
         // conditionally update queue
-        if updates & 0b010 == 0b010 {
-            (s.queue, queue_strings) = cl.queue(s.status.queue_len, &cfg.search_fields).await?;
-            s.liststate.select(None);
-            s.reselect();
-            if !s.query.is_empty() {
-                s.update_search(&queue_strings);
+        if s.connected && updates & UPDATE_QUEUE != 0 {
+            let selected = s.selected_id();
+            let res = if let Some(version) = queue_version {
+                cl.update_queue(
+                    version,
+                    s.status.queue_len,
+                    &mut s.queue,
+                    &mut queue_strings,
+                    &cfg.search_fields,
+                )
+                .await
+            } else {
+                cl.queue(s.status.queue_len, &cfg.search_fields).await.map(
+                    |(queue, new_queue_strings)| {
+                        s.queue = queue;
+                        queue_strings = new_queue_strings;
+                    },
+                )
+            };
+
+            match res {
+                Ok(()) => {
+                    queue_version = Some(s.status.version);
+                    s.update_queue(&queue_strings, selected);
+                }
+                Err(e) => {
+                    // the queue is left as it was, reload it entirely next time
+                    queue_version = None;
+                    s.handle_error(&e);
+                }
+            }
+        }
+
+        // conditionally update the browser, which also lists the stored playlists at its root
+        if s.connected
+            && (updates & UPDATE_DATABASE != 0
+                || updates & UPDATE_PLAYLISTS != 0 && s.browser.path.is_empty())
+            && views.contains(&View::Browser)
+        {
+            let res = match cl.ls(&s.browser.path).await {
+                Ok(entries) => {
+                    s.browser.list.set_items(entries);
+                    Ok(())
+                }
+                // the opened directory was deleted or moved
+                Err(e) if e.downcast_ref::<MpdError>().is_some() => {
+                    cl.ls("").await.map(|entries| s.browser.reset(entries))
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                s.handle_error(&e);
+            }
+        }
+
+        // conditionally update the library and the search results
+        if s.connected && updates & UPDATE_DATABASE != 0 {
+            if views.contains(&View::Library) {
+                if let Err(e) = s.library.reload(&mut cl, &cfg.library).await {
+                    s.handle_error(&e);
+                }
+            }
+            if let Some(expr) = &s.search.expr {
+                match cl.search(expr).await {
+                    Ok(tracks) => s.search.list.set_items(tracks),
+                    Err(e) => s.handle_error(&e),
+                }
+            }
+        }
+
+        // conditionally update stored playlists
+        if s.connected && updates & UPDATE_PLAYLISTS != 0 && views.contains(&View::Playlists) {
+            let res = if s.playlists.path.is_empty() {
+                cl.playlists()
+                    .await
+                    .map(|entries| s.playlists.list.set_items(entries))
+            } else {
+                match cl.playlist(&s.playlists.path).await {
+                    Ok(entries) => {
+                        s.playlists.list.set_items(entries);
+                        Ok(())
+                    }
+                    // the opened playlist was deleted or renamed
+                    Err(e) if e.downcast_ref::<MpdError>().is_some() => {
+                        cl.playlists().await.map(|entries| {
+                            s.playlists.leave(String::new(), entries);
+                        })
+                    }
+                    Err(e) => Err(e),
+                }
+            };
+            if let Err(e) = res {
+                s.handle_error(&e);
+            }
+        }
+
+        // conditionally update outputs
+        if s.connected && updates & UPDATE_OUTPUTS != 0 && views.contains(&View::Outputs) {
+            match cl.outputs().await {
+                Ok(outputs) => s.outputs.set_items(outputs),
+                Err(e) => s.handle_error(&e),
             }
         }
 
         // conditionally update frame
-        if updates & 0b001 == 0b001 {
-            render(&mut term, &cfg.layout, &mut s)?;
+        if updates & UPDATE_FRAME != 0 || !s.connected || s.expire_message() {
+            render(&mut term, &cfg, &mut s)?;
         }
     }
 }
@@ -1,19 +1,62 @@
-use std::io::{stdout, Write};
+mod response;
+
+use std::{
+    cmp::min,
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, stdout, IoSlice, Write},
+    net::SocketAddr,
+    path::PathBuf,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant},
+};
 
 use anyhow::{bail, Context, Result};
-use async_net::{AsyncToSocketAddrs, TcpStream};
-use expand::expand;
-use futures_lite::{
-    io::{split, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf},
-    StreamExt,
+use async_io::Timer;
+#[cfg(unix)]
+use async_net::unix::UnixStream;
+use async_net::{resolve, TcpStream};
+use dirs::home_dir;
+use futures_lite::io::{
+    split, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+    ReadHalf, WriteHalf,
 };
 use secular::lower_lay_string;
 
+use self::response::Response;
 use crate::config::SearchFields;
 
-pub struct Client {
-    r: BufReader<ReadHalf<TcpStream>>,
-    w: WriteHalf<TcpStream>,
+pub struct Client<S = Stream> {
+    r: BufReader<ReadHalf<S>>,
+    w: WriteHalf<S>,
+}
+
+#[derive(Clone)]
+pub enum Address {
+    Tcp(Vec<SocketAddr>),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+pub struct Backoff {
+    delay: Duration,
+    until: Instant,
+}
+
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+#[derive(Debug)]
+pub struct MpdError {
+    pub code: u32,
+    pub command_list_num: usize,
+    pub current_command: String,
+    pub message: String,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -29,24 +72,124 @@ pub struct Status {
     pub random: bool,
     pub single: Option<bool>, // None: oneshot
     pub consume: bool,
+    pub volume: Option<u8>, // None: no mixer
     pub queue_len: usize,
+    pub version: u32,
     pub state: PlayerState,
     pub song: Option<Song>,
+    pub next_song: Option<NextSong>,
 }
 
 #[derive(Debug)]
 pub struct Song {
     pub pos: usize,
-    pub elapsed: u16,
+    pub id: u32,
+    pub elapsed: f64,
+    pub duration: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct NextSong {
+    pub pos: usize,
+    pub id: u32,
+}
+
+/// The subsystems of mpd that `idle` waits for changes of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subsystem {
+    Database,
+    Update,
+    StoredPlaylist,
+    Playlist,
+    Player,
+    Mixer,
+    Output,
+    Options,
+    Partition,
+    Sticker,
+    Message,
+}
+
+/// The set of subsystems that changed while idling
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Changes(u16);
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 11] = [
+        Subsystem::Database,
+        Subsystem::Update,
+        Subsystem::StoredPlaylist,
+        Subsystem::Playlist,
+        Subsystem::Player,
+        Subsystem::Mixer,
+        Subsystem::Output,
+        Subsystem::Options,
+        Subsystem::Partition,
+        Subsystem::Sticker,
+        Subsystem::Message,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Subsystem::Database => "database",
+            Subsystem::Update => "update",
+            Subsystem::StoredPlaylist => "stored_playlist",
+            Subsystem::Playlist => "playlist",
+            Subsystem::Player => "player",
+            Subsystem::Mixer => "mixer",
+            Subsystem::Output => "output",
+            Subsystem::Options => "options",
+            Subsystem::Partition => "partition",
+            Subsystem::Sticker => "sticker",
+            Subsystem::Message => "message",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Subsystem> {
+        Subsystem::ALL.into_iter().find(|x| x.name() == name)
+    }
+
+    /// The subsystem mpd rejected `idle` for, older versions of mpd don't know every subsystem
+    pub fn rejected(e: &MpdError) -> Option<Subsystem> {
+        e.message
+            .strip_prefix("Unrecognized idle event: ")
+            .and_then(Subsystem::from_name)
+    }
+}
+
+impl Changes {
+    pub fn contains(self, subsystem: Subsystem) -> bool {
+        self.0 & 1 << subsystem as u16 != 0
+    }
+
+    pub fn insert(&mut self, subsystem: Subsystem) {
+        self.0 |= 1 << subsystem as u16;
+    }
 }
 
 #[derive(Debug)]
 pub struct Track {
     pub file: String,
-    pub artist: Option<String>,
-    pub album: Option<String>,
-    pub title: Option<String>,
-    pub time: u16,
+    pub id: Option<u32>,
+    pub tags: Vec<(String, String)>,
+    pub duration: Option<f64>,
+    pub priority: u8,
+}
+
+/// An audio output of mpd
+#[derive(Debug)]
+pub struct Output {
+    pub id: u32,
+    pub name: String,
+    pub plugin: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug)]
+pub enum Entry {
+    Directory(String),
+    Song(Track),
+    Playlist(String),
 }
 
 fn track_string(track: &Track, search_fields: &SearchFields) -> String {
@@ -57,33 +200,322 @@ fn track_string(track: &Track, search_fields: &SearchFields) -> String {
         track_string.push('\n');
     }
 
-    if search_fields.title {
-        if let Some(title) = &track.title {
-            track_string.push_str(&lower_lay_string(title));
-            track_string.push('\n');
+    for (enabled, name) in [
+        (search_fields.title, "Title"),
+        (search_fields.artist, "Artist"),
+        (search_fields.album, "Album"),
+    ] {
+        if enabled {
+            for value in track.tags(name) {
+                track_string.push_str(&lower_lay_string(value));
+                track_string.push('\n');
+            }
+        }
+    }
+
+    track_string
+}
+
+impl Track {
+    fn from_record(record: Vec<(String, String)>) -> Result<Track> {
+        let mut file = None;
+        let mut id = None;
+        let mut tags = Vec::with_capacity(record.len());
+        let mut duration = None;
+        let mut time = None;
+        let mut priority = 0;
+
+        for (key, value) in record {
+            match &*key {
+                "file" => file = Some(value),
+                "Id" => id = Some(value.parse()?),
+                "duration" => duration = Some(value.parse()?),
+                "Prio" => priority = value.parse()?,
+                // the position changes without the song changing, the index in the queue is used instead
+                "Pos" => {}
+                // deprecated, only used when duration is missing
+                "Time" => time = Some(value.parse::<u32>()?.into()),
+                _ => tags.push((key, value)),
+            }
+        }
+
+        let Some(file) = file else {
+            bail!("incomplete song response");
+        };
+        Ok(Track {
+            file,
+            id,
+            tags,
+            duration: duration.or(time),
+            priority,
+        })
+    }
+
+    /// All values of a tag, tag names are case insensitive
+    pub fn tags<'a: 'b, 'b>(&'a self, name: &'b str) -> impl Iterator<Item = &'a str> + 'b {
+        self.tags
+            .iter()
+            .filter(move |(tag, _)| tag.eq_ignore_ascii_case(name))
+            .map(|(_, value)| &**value)
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags(name).next()
+    }
+}
+
+impl Output {
+    fn from_record(record: Vec<(String, String)>) -> Result<Output> {
+        let mut id = None;
+        let mut name = String::new();
+        let mut plugin = String::new();
+        let mut enabled = false;
+
+        for (key, value) in record {
+            match &*key {
+                "outputid" => id = Some(value.parse()?),
+                "outputname" => name = value,
+                "plugin" => plugin = value,
+                "outputenabled" => enabled = value == "1",
+                _ => continue,
+            }
         }
+
+        let Some(id) = id else {
+            bail!("incomplete output response");
+        };
+        Ok(Output {
+            id,
+            name,
+            plugin,
+            enabled,
+        })
     }
+}
 
-    if search_fields.artist {
-        if let Some(artist) = &track.artist {
-            track_string.push_str(&lower_lay_string(artist));
-            track_string.push('\n');
+impl Entry {
+    /// The path of the entry, or the name of the stored playlist
+    pub fn uri(&self) -> &str {
+        match self {
+            Entry::Directory(path) | Entry::Playlist(path) => path,
+            Entry::Song(track) => &track.file,
         }
     }
 
-    if search_fields.album {
-        if let Some(album) = &track.album {
-            track_string.push_str(&lower_lay_string(album));
+    /// The last component of the path
+    pub fn name(&self) -> &str {
+        let uri = self.uri();
+        uri.rsplit_once('/').map_or(uri, |(_, name)| name)
+    }
+}
+
+/// A filter expression matching songs with all the tag values
+pub fn filter_expr(tags: &[(String, String)]) -> String {
+    let mut filter = String::from("(");
+    for (i, (tag, value)) in tags.iter().enumerate() {
+        if i != 0 {
+            filter.push_str(" AND ");
         }
+        filter.push_str(&format!("({tag} == {})", quote(value)));
     }
+    filter.push(')');
+    filter
+}
 
-    track_string
+/// The filter expression of a search query, plain text is searched in every tag
+pub fn search_expr(query: &str) -> String {
+    let query = query.trim();
+    if query.starts_with('(') {
+        // mpd only accepts a single expression, which can be a list joined by AND
+        format!("({query})")
+    } else {
+        format!("(any contains {})", quote(query))
+    }
+}
+
+pub fn quote(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Replaces each `{name}` in a raw command with `value(name)`, `{{` and `}}` are literal braces
+pub fn expand(
+    cmd: &str,
+    mut value: impl FnMut(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut expanded = String::with_capacity(cmd.len());
+    let mut chars = cmd.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('{') if name.is_empty() => {
+                            expanded.push('{');
+                            break;
+                        }
+                        Some('}') => {
+                            expanded.push_str(&value(&name)?);
+                            break;
+                        }
+                        Some(c) => name.push(c),
+                        None => return Err(format!("unclosed placeholder in {cmd:?}")),
+                    }
+                }
+            }
+            '}' => {
+                if chars.next() != Some('}') {
+                    return Err(format!("unmatched }} in {cmd:?}, use }}}} to escape it"));
+                }
+                expanded.push('}');
+            }
+            _ => expanded.push(c),
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Refuses raw commands that change the state of the connection itself,
+/// which would desync it from mmtc or split it from the idle connection
+pub fn check_command(cmd: &str) -> Result<(), String> {
+    let name = cmd.split_whitespace().next().unwrap_or_default();
+    if matches!(name, "idle" | "noidle" | "close" | "partition") || name.starts_with("command_list")
+    {
+        Err(format!("{name} can't be run from mmtc"))
+    } else {
+        Ok(())
+    }
+}
+
+impl Address {
+    pub async fn resolve(addr: String) -> Result<Address> {
+        if let Some(path) = addr.strip_prefix("~/") {
+            let Some(home) = home_dir() else {
+                bail!("Failed to find the home directory to expand {addr}");
+            };
+            Address::unix(home.join(path))
+        } else if addr.starts_with('/') {
+            Address::unix(addr.into())
+        } else {
+            Ok(Address::Tcp(resolve(&addr).await.with_context(|| {
+                format!("Failed to resolve address {addr}")
+            })?))
+        }
+    }
+
+    #[cfg(unix)]
+    fn unix(path: PathBuf) -> Result<Address> {
+        Ok(Address::Unix(path))
+    }
+
+    #[cfg(not(unix))]
+    fn unix(path: PathBuf) -> Result<Address> {
+        bail!(
+            "Unix domain sockets are not supported on this platform: {}",
+            path.display(),
+        );
+    }
+}
+
+impl MpdError {
+    pub const PASSWORD: u32 = 3;
+
+    // ACK [<code>@<command_list_num>] {<current_command>} <message>
+    pub fn from_ack(line: &str) -> MpdError {
+        fn parse(line: &str) -> Option<MpdError> {
+            let (code, line) = line.strip_prefix("ACK [")?.split_once('@')?;
+            let (command_list_num, line) = line.split_once("] {")?;
+            let (current_command, message) = line.split_once('}')?;
+            Some(MpdError {
+                code: code.parse().ok()?,
+                command_list_num: command_list_num.parse().ok()?,
+                current_command: current_command.into(),
+                message: message.trim_start().into(),
+            })
+        }
+
+        parse(line).unwrap_or_else(|| MpdError {
+            code: 0,
+            command_list_num: 0,
+            current_command: String::new(),
+            message: line.into(),
+        })
+    }
+}
+
+impl Display for MpdError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match (&*self.current_command, self.command_list_num) {
+            ("", _) => f.write_str(&self.message),
+            (cmd, 0) => write!(f, "{cmd}: {}", self.message),
+            (cmd, n) => write!(f, "{cmd} (command {n} in the list): {}", self.message),
+        }
+    }
+}
+
+impl Error for MpdError {}
+
+impl Backoff {
+    const MAX: Duration = Duration::from_secs(10);
+    const MIN: Duration = Duration::from_millis(250);
+
+    pub fn new() -> Backoff {
+        Backoff {
+            delay: Backoff::MIN,
+            until: Instant::now(),
+        }
+    }
+
+    pub fn ready(&self) -> bool {
+        Instant::now() >= self.until
+    }
+
+    pub fn fail(&mut self) {
+        self.until = Instant::now() + self.delay;
+        self.delay = min(self.delay * 2, Backoff::MAX);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Backoff::new();
+    }
+
+    pub fn wait(&self) -> Timer {
+        Timer::at(self.until)
+    }
 }
 
 impl Client {
-    pub async fn init(addr: impl AsyncToSocketAddrs) -> Result<Client> {
+    pub async fn init(addr: &Address, password: Option<&str>) -> Result<Client> {
+        let stream = match addr {
+            Address::Tcp(addr) => TcpStream::connect(&**addr).await.map(Stream::Tcp),
+            #[cfg(unix)]
+            Address::Unix(path) => UnixStream::connect(path).await.map(Stream::Unix),
+        };
+
+        let mut cl = Client::new(stream.context("Failed to connect to mpd")?).await?;
+        if let Some(password) = password {
+            cl.password(password).await?;
+        }
+
+        Ok(cl)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Client<S> {
+    pub async fn new(stream: S) -> Result<Client<S>> {
         async move {
-            let (r, w) = split(TcpStream::connect(addr).await?);
+            let (r, w) = split(stream);
             let mut cl = Client {
                 r: BufReader::new(r),
                 w,
@@ -102,24 +534,54 @@ impl Client {
         .context("Failed to init client")
     }
 
-    pub async fn idle(&mut self) -> Result<(bool, bool)> {
+    async fn request(&mut self, cmd: &[u8]) -> Result<Response<'_, BufReader<ReadHalf<S>>>> {
+        self.w.write_all(cmd).await?;
+        self.w.write_all(b"\n").await?;
+        Ok(Response::new(&mut self.r))
+    }
+
+    pub async fn password(&mut self, password: &str) -> Result<()> {
         async move {
-            self.w.write_all(b"idle options player playlist\n").await?;
-            let mut lines = (&mut self.r).lines();
-            let mut status = false;
-            let mut queue = false;
-
-            while let Some(line) = lines.next().await {
-                match line?.as_bytes() {
-                    b"changed: options" => status = true,
-                    b"changed: player" => status = true,
-                    b"changed: playlist" => queue = true,
-                    b"OK" => break,
-                    _ => continue,
+            let cmd = format!("password {}", quote(password));
+            match self.request(cmd.as_bytes()).await?.finish().await {
+                Err(e)
+                    if matches!(
+                        e.downcast_ref(),
+                        Some(MpdError {
+                            code: MpdError::PASSWORD,
+                            ..
+                        })
+                    ) =>
+                {
+                    Err(e.context("incorrect password"))
+                }
+                res => res,
+            }
+        }
+        .await
+        .context("Failed to authenticate")
+    }
+
+    pub async fn idle(&mut self, subsystems: &[Subsystem]) -> Result<Changes> {
+        async move {
+            let mut cmd = String::from("idle");
+            for subsystem in subsystems {
+                cmd.push(' ');
+                cmd.push_str(subsystem.name());
+            }
+
+            let mut res = self.request(cmd.as_bytes()).await?;
+            let mut changes = Changes::default();
+
+            while let Some((key, value)) = res.next().await? {
+                if key == "changed" {
+                    if let Some(subsystem) = Subsystem::from_name(value) {
+                        changes.insert(subsystem);
+                    }
                 }
             }
 
-            Result::<_>::Ok((status, queue))
+            Result::<_>::Ok(changes)
         }
         .await
         .context("Failed to idle")
@@ -131,70 +593,309 @@ impl Client {
         search_fields: &SearchFields,
     ) -> Result<(Vec<Track>, Vec<String>)> {
         async move {
-            let mut first = true;
             let mut tracks = Vec::with_capacity(len);
             let mut track_strings = Vec::with_capacity(len);
+            let mut res = self.request(b"playlistinfo").await?;
 
-            let mut file = None;
-            let mut artist = None;
-            let mut album = None;
-            let mut title = None;
-            let mut time = 0;
-
-            self.w.write_all(b"playlistinfo\n").await?;
-            let mut lines = (&mut self.r).lines();
-
-            while let Some(line) = lines.next().await {
-                let line = line?;
-                match line.as_bytes() {
-                    b"OK" => break,
-                    expand!([@b"file: ", ..]) => {
-                        if first {
-                            first = false;
-                        } else if let Some(file) = file {
-                            let track = Track {
-                                file,
-                                artist,
-                                album,
-                                title,
-                                time,
-                            };
-                            track_strings.push(track_string(&track, search_fields));
-                            tracks.push(track);
-                        } else {
-                            bail!("incomplete playlist response");
-                        }
+            while let Some(record) = res.record(&["file"]).await? {
+                let track = Track::from_record(record)?;
+                track_strings.push(track_string(&track, search_fields));
+                tracks.push(track);
+            }
 
-                        file = Some(line[6 ..].into());
-                        artist = None;
-                        album = None;
-                        title = None;
-                        time = 0;
+            Result::<_>::Ok((tracks, track_strings))
+        }
+        .await
+        .context("Failed to query queue")
+    }
+
+    /// Patches the queue with the changes since `version`,
+    /// reusing known tracks and only querying the songs that are new to the queue
+    pub async fn update_queue(
+        &mut self,
+        version: u32,
+        len: usize,
+        queue: &mut Vec<Track>,
+        queue_strings: &mut Vec<String>,
+        search_fields: &SearchFields,
+    ) -> Result<()> {
+        async move {
+            let mut changes = Vec::new();
+            let mut pos = None;
+            let mut res = self
+                .request(format!("plchangesposid {version}").as_bytes())
+                .await?;
+            while let Some((key, value)) = res.next().await? {
+                match key {
+                    "cpos" => pos = Some(value.parse::<usize>()?),
+                    "Id" => {
+                        if let Some(pos) = pos.take() {
+                            changes.push((pos, value.parse::<u32>()?));
+                        }
                     }
-                    expand!([@b"Artist: ", ..]) => artist = Some(line[8 ..].into()),
-                    expand!([@b"Album: ", ..]) => album = Some(line[7 ..].into()),
-                    expand!([@b"Title: ", ..]) => title = Some(line[7 ..].into()),
-                    expand!([@b"Time: ", ..]) => time = line[6 ..].parse()?,
                     _ => continue,
                 }
             }
+            changes.retain(|&(pos, _)| pos < len);
+            changes.sort_unstable_by_key(|&(pos, _)| pos);
 
-            if let Some(file) = file {
-                let track = Track {
-                    file,
-                    artist,
-                    album,
-                    title,
-                    time,
+            let ids: HashMap<_, _> = queue
+                .iter()
+                .enumerate()
+                .filter_map(|(i, track)| Some((track.id?, i)))
+                .collect();
+
+            let mut fetched = HashMap::new();
+            let mut cmd = String::from("command_list_begin\n");
+            for (_, id) in &changes {
+                if !ids.contains_key(id) {
+                    cmd.push_str("playlistid ");
+                    cmd.push_str(&id.to_string());
+                    cmd.push('\n');
+                }
+            }
+            if cmd.len() > "command_list_begin\n".len() {
+                cmd.push_str("command_list_end");
+                let mut res = self.request(cmd.as_bytes()).await?;
+                while let Some(record) = res.record(&["file"]).await? {
+                    let track = Track::from_record(record)?;
+                    if let Some(id) = track.id {
+                        fetched.insert(id, track);
+                    }
+                }
+            }
+
+            // work out where every track comes from before touching the queue,
+            // so a failed reload leaves the old queue intact
+            let mut taken = vec![false; queue.len()];
+            let mut sources = Vec::with_capacity(len);
+            let mut changes = changes.into_iter().peekable();
+            for pos in 0 .. len {
+                let source = if let Some((_, id)) = changes.next_if(|&(x, _)| x == pos) {
+                    match ids.get(&id) {
+                        Some(&i) => Some(Ok(i)),
+                        None => fetched.remove(&id).map(Err),
+                    }
+                } else {
+                    Some(Ok(pos))
                 };
-                track_strings.push(track_string(&track, search_fields));
-                tracks.push(track);
+
+                match source {
+                    Some(Ok(i)) if taken.get(i) == Some(&false) => {
+                        taken[i] = true;
+                        sources.push(Ok(i));
+                    }
+                    Some(Err(track)) => sources.push(Err(track)),
+                    _ => {
+                        // the changes are inconsistent with the current queue, reload everything
+                        (*queue, *queue_strings) = self.queue(len, search_fields).await?;
+                        return Ok(());
+                    }
+                }
             }
 
-            Ok((tracks, track_strings))
+            let mut old: Vec<_> = queue
+                .drain(..)
+                .zip(queue_strings.drain(..))
+                .map(Some)
+                .collect();
+            for source in sources {
+                let entry = match source {
+                    Ok(i) => old[i].take(),
+                    Err(track) => {
+                        let track_string = track_string(&track, search_fields);
+                        Some((track, track_string))
+                    }
+                };
+                if let Some((track, track_string)) = entry {
+                    queue.push(track);
+                    queue_strings.push(track_string);
+                }
+            }
+
+            Result::<_>::Ok(())
         }
         .await
-        .context("Failed to query queue")
+        .context("Failed to update queue")
+    }
+
+    /// Lists the directories, songs and playlists in a directory of the database
+    pub async fn ls(&mut self, path: &str) -> Result<Vec<Entry>> {
+        async move {
+            let mut entries = Vec::new();
+            let mut res = self
+                .request(format!("lsinfo {}", quote(path)).as_bytes())
+                .await?;
+
+            while let Some(record) = res.record(&["directory", "file", "playlist"]).await? {
+                let (key, value) = &record[0];
+                entries.push(match &**key {
+                    "directory" => Entry::Directory(value.clone()),
+                    "playlist" => Entry::Playlist(value.clone()),
+                    _ => Entry::Song(Track::from_record(record)?),
+                });
+            }
+
+            Result::<_>::Ok(entries)
+        }
+        .await
+        .with_context(|| format!("Failed to list directory {path:?}"))
+    }
+
+    /// Lists the stored playlists
+    pub async fn playlists(&mut self) -> Result<Vec<Entry>> {
+        async move {
+            let mut entries = Vec::new();
+            let mut res = self.request(b"listplaylists").await?;
+
+            while let Some((key, value)) = res.next().await? {
+                if key == "playlist" {
+                    entries.push(Entry::Playlist(value.into()));
+                }
+            }
+
+            Result::<_>::Ok(entries)
+        }
+        .await
+        .context("Failed to list playlists")
+    }
+
+    /// Lists the songs in a stored playlist
+    pub async fn playlist(&mut self, name: &str) -> Result<Vec<Entry>> {
+        async move {
+            let mut entries = Vec::new();
+            let mut res = self
+                .request(format!("listplaylistinfo {}", quote(name)).as_bytes())
+                .await?;
+
+            while let Some(record) = res.record(&["file"]).await? {
+                entries.push(Entry::Song(Track::from_record(record)?));
+            }
+
+            Result::<_>::Ok(entries)
+        }
+        .await
+        .with_context(|| format!("Failed to list playlist {name:?}"))
+    }
+
+    pub async fn outputs(&mut self) -> Result<Vec<Output>> {
+        async move {
+            let mut outputs = Vec::new();
+            let mut res = self.request(b"outputs").await?;
+
+            while let Some(record) = res.record(&["outputid"]).await? {
+                outputs.push(Output::from_record(record)?);
+            }
+
+            Result::<_>::Ok(outputs)
+        }
+        .await
+        .context("Failed to query outputs")
+    }
+
+    /// Lists the values of the last tag of `tags` among the songs matching `filter`,
+    /// along with the values of the other tags they are grouped by
+    pub async fn list(
+        &mut self,
+        tags: &[String],
+        filter: &[(String, String)],
+    ) -> Result<Vec<Vec<(String, String)>>> {
+        async move {
+            let Some((tag, groups)) = tags.split_last() else {
+                return Ok(Vec::new());
+            };
+
+            let mut cmd = format!("list {tag}");
+            if !filter.is_empty() {
+                cmd.push(' ');
+                cmd.push_str(&quote(&filter_expr(filter)));
+            }
+            for group in groups {
+                cmd.push_str(" group ");
+                cmd.push_str(group);
+            }
+
+            // mpd only sends the group values when they change
+            let mut values = vec![String::new(); groups.len()];
+            let mut rows = Vec::new();
+            let mut res = self.request(cmd.as_bytes()).await?;
+            while let Some((key, value)) = res.next().await? {
+                if key.eq_ignore_ascii_case(tag) {
+                    let mut row: Vec<_> = groups.iter().cloned().zip(values.clone()).collect();
+                    row.push((tag.clone(), value.into()));
+                    rows.push(row);
+                } else if let Some(i) = groups
+                    .iter()
+                    .position(|group| key.eq_ignore_ascii_case(group))
+                {
+                    values[i] = value.into();
+                }
+            }
+
+            Result::<_>::Ok(rows)
+        }
+        .await
+        .with_context(|| format!("Failed to list {}", tags.join(", ")))
+    }
+
+    /// Finds the songs with all the tag values
+    pub async fn find(&mut self, filter: &[(String, String)]) -> Result<Vec<Track>> {
+        async move {
+            let mut tracks = Vec::new();
+            let mut res = self
+                .request(format!("find {}", quote(&filter_expr(filter))).as_bytes())
+                .await?;
+
+            while let Some(record) = res.record(&["file"]).await? {
+                tracks.push(Track::from_record(record)?);
+            }
+
+            Result::<_>::Ok(tracks)
+        }
+        .await
+        .context("Failed to find songs")
+    }
+
+    /// Searches the database with a filter expression
+    pub async fn search(&mut self, expr: &str) -> Result<Vec<Track>> {
+        async move {
+            let mut tracks = Vec::new();
+            let mut res = self
+                .request(format!("search {}", quote(expr)).as_bytes())
+                .await?;
+
+            while let Some(record) = res.record(&["file"]).await? {
+                tracks.push(Track::from_record(record)?);
+            }
+
+            Result::<_>::Ok(tracks)
+        }
+        .await
+        .with_context(|| format!("Failed to search {expr}"))
+    }
+
+    /// Adds a song to the queue and returns its id
+    pub async fn add_id(&mut self, uri: &str) -> Result<u32> {
+        let mut res = self
+            .request(format!("addid {}", quote(uri)).as_bytes())
+            .await?;
+        let mut id = None;
+        while let Some((key, value)) = res.next().await? {
+            if key == "Id" {
+                id = Some(value.parse()?);
+            }
+        }
+        id.context("mpd did not return the id of the new song")
+    }
+
+    /// Adds a song to the end of the queue and plays it
+    pub async fn add_and_play(&mut self, uri: &str) -> Result<()> {
+        async move {
+            let id = self.add_id(uri).await?;
+            self.play_id(id).await
+        }
+        .await
+        .context("Failed to play the selected song")
     }
 
     pub async fn status(&mut self) -> Result<Status> {
@@ -203,50 +904,70 @@ impl Client {
             let mut random = None;
             let mut single = None;
             let mut consume = None;
+            let mut volume = None;
             let mut queue_len = None;
+            let mut version = None;
             let mut state = PlayerState::Stop;
             let mut pos = None;
+            let mut id = None;
             let mut elapsed = None;
+            let mut duration = None;
+            let mut next_pos = None;
+            let mut next_id = None;
 
-            self.w.write_all(b"status\n").await?;
-            let mut lines = (&mut self.r).lines();
-
-            while let Some(line) = lines.next().await {
-                let line = line?;
-                match line.as_bytes() {
-                    b"OK" => break,
-                    b"repeat: 0" => repeat = Some(false),
-                    b"repeat: 1" => repeat = Some(true),
-                    b"random: 0" => random = Some(false),
-                    b"random: 1" => random = Some(true),
-                    b"single: 0" => single = Some(Some(false)),
-                    b"single: 1" => single = Some(Some(true)),
-                    b"single: oneshot" => single = Some(None),
-                    b"consume: 0" => consume = Some(false),
-                    b"consume: 1" => consume = Some(true),
-                    expand!([@b"playlistlength: ", ..]) => queue_len = Some(line[16 ..].parse()?),
-                    b"state: play" => state = PlayerState::Play,
-                    b"state: pause" => state = PlayerState::Pause,
-                    expand!([@b"song: ", ..]) => pos = Some(line[6 ..].parse()?),
-                    expand!([@b"elapsed: ", ..]) => {
-                        elapsed = Some(line[9 ..].parse::<f32>()?.round() as u16)
-                    }
+            let mut res = self.request(b"status").await?;
+            while let Some((key, value)) = res.next().await? {
+                match (key, value) {
+                    ("repeat", _) => repeat = Some(value == "1"),
+                    ("random", _) => random = Some(value == "1"),
+                    ("single", "oneshot") => single = Some(None),
+                    ("single", _) => single = Some(Some(value == "1")),
+                    ("consume", _) => consume = Some(value == "1"),
+                    ("volume", _) => volume = u8::try_from(value.parse::<i32>()?).ok(),
+                    ("playlistlength", _) => queue_len = Some(value.parse()?),
+                    ("playlist", _) => version = Some(value.parse()?),
+                    ("state", "play") => state = PlayerState::Play,
+                    ("state", "pause") => state = PlayerState::Pause,
+                    ("song", _) => pos = Some(value.parse()?),
+                    ("songid", _) => id = Some(value.parse()?),
+                    ("nextsong", _) => next_pos = Some(value.parse()?),
+                    ("nextsongid", _) => next_id = Some(value.parse()?),
+                    ("elapsed", _) => elapsed = Some(value.parse()?),
+                    ("duration", _) => duration = Some(value.parse()?),
                     _ => continue,
                 }
             }
 
-            if let (Some(repeat), Some(random), Some(single), Some(consume), Some(queue_len)) =
-                (repeat, random, single, consume, queue_len)
+            if let (
+                Some(repeat),
+                Some(random),
+                Some(single),
+                Some(consume),
+                Some(queue_len),
+                Some(version),
+            ) = (repeat, random, single, consume, queue_len, version)
             {
                 Ok(Status {
                     repeat,
                     random,
                     single,
                     consume,
+                    volume,
                     queue_len,
+                    version,
                     state,
-                    song: if let (Some(pos), Some(elapsed)) = (pos, elapsed) {
-                        Some(Song { pos, elapsed })
+                    song: if let (Some(pos), Some(id), Some(elapsed)) = (pos, id, elapsed) {
+                        Some(Song {
+                            pos,
+                            id,
+                            elapsed,
+                            duration,
+                        })
+                    } else {
+                        None
+                    },
+                    next_song: if let (Some(pos), Some(id)) = (next_pos, next_id) {
+                        Some(NextSong { pos, id })
                     } else {
                         None
                     },
@@ -260,55 +981,429 @@ impl Client {
     }
 
     pub async fn play(&mut self, pos: usize) -> Result<()> {
-        self.w.write_all(b"play ").await?;
-        self.w.write_all(pos.to_string().as_bytes()).await?;
-        self.w.write_all(b"\n").await?;
-        let mut lines = (&mut self.r).lines();
+        self.command(format!("play {pos}").as_bytes()).await
+    }
 
-        while let Some(line) = lines.next().await {
-            match line?.as_bytes() {
-                b"OK" | expand!([@b"ACK ", ..]) => break,
-                _ => continue,
-            }
+    pub async fn play_id(&mut self, id: u32) -> Result<()> {
+        self.command(format!("playid {id}").as_bytes()).await
+    }
+
+    /// Adds a directory or a song to the queue, right after the current song if `insert`
+    pub async fn add(&mut self, uri: &str, insert: bool) -> Result<()> {
+        let cmd = format!("add {}{}", quote(uri), if insert { " +0" } else { "" });
+        self.command(cmd.as_bytes()).await
+    }
+
+    /// Adds a stored playlist to the queue, right after the current song if `insert`
+    pub async fn load(&mut self, name: &str, insert: bool) -> Result<()> {
+        let cmd = format!("load {}{}", quote(name), if insert { " 0: +0" } else { "" });
+        self.command(cmd.as_bytes()).await
+    }
+
+    /// Adds the songs with all the tag values, right after the current song if `insert`
+    pub async fn find_add(&mut self, filter: &[(String, String)], insert: bool) -> Result<()> {
+        let cmd = format!(
+            "findadd {}{}",
+            quote(&filter_expr(filter)),
+            if insert { " position +0" } else { "" },
+        );
+        self.command(cmd.as_bytes()).await
+    }
+
+    /// Shuffles the songs from `start` up to but not including `end`
+    pub async fn shuffle(&mut self, start: usize, end: usize) -> Result<()> {
+        self.command(format!("shuffle {start}:{end}").as_bytes())
+            .await
+    }
+
+    /// Deletes every song except the one at `pos`
+    pub async fn crop(&mut self, pos: usize, len: usize) -> Result<()> {
+        if pos >= len {
+            return Ok(());
         }
 
-        Ok(())
+        // older versions of mpd reject empty ranges
+        let mut cmds = Vec::with_capacity(2);
+        if pos + 1 < len {
+            cmds.push(format!("delete {}:{len}", pos + 1));
+        }
+        if pos > 0 {
+            cmds.push(format!("delete 0:{pos}"));
+        }
+        self.command_list(&cmds).await
+    }
+
+    pub async fn set_volume(&mut self, volume: u8) -> Result<()> {
+        self.command(format!("setvol {volume}").as_bytes()).await
+    }
+
+    /// Changes the volume relative to the current volume known by mpd
+    pub async fn change_volume(&mut self, change: i16) -> Result<()> {
+        self.command(format!("volume {change:+}").as_bytes()).await
+    }
+
+    pub async fn seek(&mut self, secs: f64) -> Result<()> {
+        self.command(format!("seekcur {secs:.3}").as_bytes()).await
     }
 
     pub async fn command(&mut self, cmd: &[u8]) -> Result<()> {
-        self.w.write_all(cmd).await?;
-        self.w.write_all(b"\n").await?;
-        let mut lines = (&mut self.r).lines();
+        self.request(cmd).await?.finish().await
+    }
 
-        while let Some(line) = lines.next().await {
-            match line?.as_bytes() {
-                b"OK" | expand!([@b"ACK ", ..]) => break,
-                _ => continue,
+    /// Sends the commands in a single command list, does nothing if there are no commands
+    pub async fn command_list(&mut self, cmds: &[String]) -> Result<()> {
+        if cmds.is_empty() {
+            return Ok(());
+        }
+
+        let mut cmd = String::from("command_list_begin\n");
+        for x in cmds {
+            cmd.push_str(x);
+            cmd.push('\n');
+        }
+        cmd.push_str("command_list_end");
+        self.command(cmd.as_bytes()).await
+    }
+
+    /// Runs a raw command and collects the key value pairs of the response, without binary data
+    pub async fn command_pairs(&mut self, cmd: &[u8]) -> Result<Vec<(String, String)>> {
+        let mut pairs = Vec::new();
+        let mut res = self.request(cmd).await?;
+
+        while let Some((key, value)) = res.next().await? {
+            pairs.push((key.into(), value.into()));
+        }
+
+        Ok(pairs)
+    }
+
+    pub async fn command_stdout(&mut self, cmd: &[u8]) -> Result<()> {
+        let mut stdout = stdout().lock();
+        let mut res = self.request(cmd).await?;
+
+        while let Some((key, value)) = res.next().await? {
+            if key == "list_OK" {
+                writeln!(stdout, "{key}")?;
+            } else {
+                writeln!(stdout, "{key}: {value}")?;
+            }
+            if key == "binary" {
+                stdout.write_all(res.binary())?;
+                writeln!(stdout)?;
             }
         }
+        writeln!(stdout, "OK")?;
 
         Ok(())
     }
+}
 
-    pub async fn command_stdout(&mut self, cmd: &[u8]) -> Result<()> {
-        self.w.write_all(cmd).await?;
-        self.w.write_all(b"\n").await?;
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
 
-        let mut stdout = stdout().lock();
-        let mut lines = (&mut self.r).lines();
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
 
-        while let Some(line) = lines.next().await {
-            let line = line?;
-            let line = line.as_bytes();
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext,
+        bufs: &[IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
 
-            stdout.write_all(line)?;
-            stdout.write_all(b"\n")?;
-            match line {
-                b"OK" | expand!([@b"ACK ", ..]) => break,
-                _ => continue,
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_close(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_close(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        io::{self, Read},
+        pin::Pin,
+        rc::Rc,
+        task::{Context as TaskContext, Poll},
+    };
+
+    use async_io::block_on;
+    use futures_lite::io::{AsyncRead, AsyncWrite};
+
+    use super::{expand, filter_expr, search_expr, Changes, Client, Subsystem, Track};
+    use crate::defaults;
+
+    /// A connection to mpd that answers with a fixed transcript and records the commands
+    struct Transcript {
+        responses: &'static [u8],
+        commands: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl AsyncRead for Transcript {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut TaskContext,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(self.responses.read(buf))
+        }
+    }
+
+    impl AsyncWrite for Transcript {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut TaskContext,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.commands.borrow_mut().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut TaskContext) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut TaskContext) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// A client connected to a fake mpd that answers with `responses`, and the commands it sent
+    fn client(responses: &str) -> (Client<Transcript>, Rc<RefCell<Vec<u8>>>) {
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        let stream = Transcript {
+            responses: format!("OK MPD 0.23.5\n{responses}").leak().as_bytes(),
+            commands: Rc::clone(&commands),
+        };
+        (block_on(Client::new(stream)).unwrap(), commands)
+    }
+
+    /// Updates a queue of songs named after their ids to `len` songs with the responses,
+    /// returns the files, the search strings, and the commands sent
+    fn update_queue(
+        ids: &[u32],
+        len: usize,
+        responses: &'static str,
+    ) -> (Vec<String>, Vec<String>, String) {
+        let (mut cl, commands) = client(responses);
+        let mut queue: Vec<_> = ids
+            .iter()
+            .map(|&id| Track {
+                file: id.to_string(),
+                id: Some(id),
+                tags: Vec::new(),
+                duration: None,
+                priority: 0,
+            })
+            .collect();
+        let mut queue_strings: Vec<_> = ids.iter().map(u32::to_string).collect();
+
+        block_on(async {
+            cl.update_queue(
+                7,
+                len,
+                &mut queue,
+                &mut queue_strings,
+                &defaults::search_fields(),
+            )
+            .await
+            .unwrap();
+        });
+
+        assert!(queue.iter().all(|track| track.tag("Pos").is_none()));
+        let commands = String::from_utf8(commands.take()).unwrap();
+        (
+            queue.into_iter().map(|track| track.file).collect(),
+            queue_strings,
+            commands,
+        )
+    }
+
+    #[test]
+    fn crop() {
+        for (pos, len, expected) in [
+            (0, 1, ""),
+            (3, 3, ""),
+            (0, 3, "command_list_begin\ndelete 1:3\ncommand_list_end\n"),
+            (2, 3, "command_list_begin\ndelete 0:2\ncommand_list_end\n"),
+            (
+                1,
+                3,
+                "command_list_begin\ndelete 2:3\ndelete 0:1\ncommand_list_end\n",
+            ),
+        ] {
+            let (mut cl, commands) = client("OK\n");
+            block_on(cl.crop(pos, len)).unwrap();
+            assert_eq!(String::from_utf8(commands.take()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn queue_inserts() {
+        let (files, strings, commands) = update_queue(
+            &[1, 2, 3],
+            4,
+            "cpos: 1\nId: 4\ncpos: 2\nId: 2\ncpos: 3\nId: 3\nOK\n\
+             file: 4\nTitle: New\nPos: 1\nId: 4\nOK\n",
+        );
+        assert_eq!(files, ["1", "4", "2", "3"]);
+        assert_eq!(strings, ["1", "new\n", "2", "3"]);
+        assert_eq!(
+            commands,
+            "plchangesposid 7\ncommand_list_begin\nplaylistid 4\ncommand_list_end\n",
+        );
+    }
+
+    #[test]
+    fn queue_deletes() {
+        let (files, strings, commands) = update_queue(&[1, 2, 3, 4], 2, "cpos: 1\nId: 4\nOK\n");
+        assert_eq!(files, ["1", "4"]);
+        assert_eq!(strings, ["1", "4"]);
+        assert_eq!(commands, "plchangesposid 7\n");
+    }
+
+    #[test]
+    fn queue_moves() {
+        let (files, _, commands) = update_queue(
+            &[1, 2, 3],
+            3,
+            "cpos: 0\nId: 3\ncpos: 1\nId: 1\ncpos: 2\nId: 2\nOK\n",
+        );
+        assert_eq!(files, ["3", "1", "2"]);
+        assert_eq!(commands, "plchangesposid 7\n");
+    }
+
+    #[test]
+    fn queue_shrinks() {
+        // changes past the end of the queue are from before it shrunk
+        let (files, _, commands) = update_queue(&[1, 2, 3], 1, "cpos: 2\nId: 9\nOK\n");
+        assert_eq!(files, ["1"]);
+        assert_eq!(commands, "plchangesposid 7\n");
+    }
+
+    #[test]
+    fn queue_reloads() {
+        // the song at position 1 moved to 0 without a change for position 1
+        let (files, strings, commands) = update_queue(
+            &[1, 2, 3],
+            3,
+            "cpos: 0\nId: 2\nOK\n\
+             file: 2\nPos: 0\nId: 2\nfile: 1\nPos: 1\nId: 1\nfile: 3\nPos: 2\nId: 3\nOK\n",
+        );
+        assert_eq!(files, ["2", "1", "3"]);
+        assert_eq!(strings, ["", "", ""]);
+        assert_eq!(commands, "plchangesposid 7\nplaylistinfo\n");
+    }
+
+    #[test]
+    fn queue_reload_fails() {
+        let (mut cl, _) = client("cpos: 0\nId: 2\nOK\nACK [5@0] {playlistinfo} failed\n");
+        let mut queue: Vec<_> = (1 ..= 3)
+            .map(|id| Track {
+                file: id.to_string(),
+                id: Some(id),
+                tags: Vec::new(),
+                duration: None,
+                priority: 0,
+            })
+            .collect();
+        let mut queue_strings = vec![String::new(); 3];
+
+        let res = block_on(cl.update_queue(
+            7,
+            3,
+            &mut queue,
+            &mut queue_strings,
+            &defaults::search_fields(),
+        ));
+        assert!(res.is_err());
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue_strings.len(), 3);
+    }
+
+    #[test]
+    fn filter() {
+        assert_eq!(
+            filter_expr(&[("Artist".into(), "a \"b\"".into())]),
+            r#"((Artist == "a \"b\""))"#,
+        );
+        assert_eq!(
+            filter_expr(&[("Date".into(), "2000".into()), ("Album".into(), "c".into())]),
+            r#"((Date == "2000") AND (Album == "c"))"#,
+        );
+    }
+
+    #[test]
+    fn placeholders() {
+        let value = |name: &str| match name {
+            "file" => Ok(String::from("\"a b.flac\"")),
+            _ => Err(format!("unknown placeholder {{{name}}}")),
+        };
+        assert_eq!(
+            expand("playlistadd {{x}} {file}", value),
+            Ok(String::from("playlistadd {x} \"a b.flac\"")),
+        );
+        assert!(expand("add {id}", value).is_err());
+        assert!(expand("add {file", value).is_err());
+        assert!(expand("add }", value).is_err());
+    }
+
+    #[test]
+    fn subsystems() {
+        let mut changes = Changes::default();
+        for name in ["stored_playlist", "output", "unknown"] {
+            if let Some(subsystem) = Subsystem::from_name(name) {
+                changes.insert(subsystem);
             }
         }
+        assert!(changes.contains(Subsystem::StoredPlaylist));
+        assert!(changes.contains(Subsystem::Output));
+        assert!(!changes.contains(Subsystem::Playlist));
+    }
 
-        Ok(())
+    #[test]
+    fn search() {
+        assert_eq!(search_expr(" abc "), r#"(any contains "abc")"#);
+        assert_eq!(
+            search_expr("(artist contains 'x') AND (date >= '2000')"),
+            "((artist contains 'x') AND (date >= '2000'))",
+        );
     }
 }
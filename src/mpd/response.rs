@@ -0,0 +1,211 @@
+use std::str;
+
+use anyhow::{bail, Context, Result};
+use futures_lite::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use crate::mpd::MpdError;
+
+/// Reads a response from mpd as key value pairs
+pub struct Response<'a, R> {
+    r: &'a mut R,
+    line: Vec<u8>,
+    sep: usize,
+    binary: Vec<u8>,
+    peeked: bool,
+    done: bool,
+}
+
+impl<'a, R: AsyncBufRead + Unpin> Response<'a, R> {
+    pub fn new(r: &'a mut R) -> Response<'a, R> {
+        Response {
+            r,
+            line: Vec::with_capacity(64),
+            sep: 0,
+            binary: Vec::new(),
+            peeked: false,
+            done: false,
+        }
+    }
+
+    /// Returns the next key value pair, or `None` once mpd responds with `OK`
+    ///
+    /// `list_OK` is returned as a pair with an empty value,
+    /// and the data after `binary` is available from [`Response::binary`]
+    pub async fn next(&mut self) -> Result<Option<(&str, &str)>> {
+        if self.peeked {
+            self.peeked = false;
+        } else {
+            if self.done {
+                return Ok(None);
+            }
+
+            self.line.clear();
+            if self.r.read_until(b'\n', &mut self.line).await? == 0 {
+                bail!("connection closed");
+            }
+            if self.line.pop() != Some(b'\n') {
+                bail!("connection closed");
+            }
+
+            match &self.line[..] {
+                b"OK" => {
+                    self.done = true;
+                    return Ok(None);
+                }
+                b"list_OK" => self.sep = self.line.len(),
+                [b'A', b'C', b'K', b' ', ..] => {
+                    self.done = true;
+                    let line = String::from_utf8_lossy(&self.line);
+                    return Err(MpdError::from_ack(&line).into());
+                }
+                line => {
+                    self.sep = line
+                        .windows(2)
+                        .position(|xs| xs == b": ")
+                        .with_context(|| {
+                            format!("malformed response: {}", String::from_utf8_lossy(line))
+                        })?;
+                }
+            }
+
+            if &self.line[.. self.sep] == b"binary" {
+                let len = str::from_utf8(&self.line[self.sep + 2 ..])?.parse()?;
+                self.binary.resize(len, 0);
+                self.r.read_exact(&mut self.binary).await?;
+                let mut newline = [0];
+                self.r.read_exact(&mut newline).await?;
+            }
+        }
+
+        let key = str::from_utf8(&self.line[.. self.sep])?;
+        let value = str::from_utf8(self.line.get(self.sep + 2 ..).unwrap_or_default())?;
+        Ok(Some((key, value)))
+    }
+
+    /// Returns all pairs until the next key in `boundaries`,
+    /// or `None` once mpd responds with `OK`
+    pub async fn record(&mut self, boundaries: &[&str]) -> Result<Option<Vec<(String, String)>>> {
+        let Some((key, value)) = self.next().await? else {
+            return Ok(None);
+        };
+        let mut record = vec![(key.into(), value.into())];
+
+        while let Some((key, value)) = self.next().await? {
+            if boundaries.contains(&key) {
+                self.peeked = true;
+                break;
+            }
+            record.push((key.into(), value.into()));
+        }
+
+        Ok(Some(record))
+    }
+
+    /// The data of the last `binary` pair
+    pub fn binary(&self) -> &[u8] {
+        &self.binary
+    }
+
+    /// Skips the rest of the response
+    pub async fn finish(&mut self) -> Result<()> {
+        while self.next().await?.is_some() {}
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_io::block_on;
+
+    use super::Response;
+    use crate::mpd::MpdError;
+
+    #[test]
+    fn pairs() {
+        let r = &mut &b"volume: 42\nrepeat: 0\nstate: play\nOK\n"[..];
+        let mut res = Response::new(r);
+        block_on(async {
+            assert_eq!(res.next().await.unwrap(), Some(("volume", "42")));
+            assert_eq!(res.next().await.unwrap(), Some(("repeat", "0")));
+            assert_eq!(res.next().await.unwrap(), Some(("state", "play")));
+            assert_eq!(res.next().await.unwrap(), None);
+            assert_eq!(res.next().await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn values_with_separators() {
+        let r = &mut &b"Title: a: b\nComment: \nOK\n"[..];
+        let mut res = Response::new(r);
+        block_on(async {
+            assert_eq!(res.next().await.unwrap(), Some(("Title", "a: b")));
+            assert_eq!(res.next().await.unwrap(), Some(("Comment", "")));
+            assert_eq!(res.next().await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn records() {
+        let r = &mut &b"\
+file: a.flac
+Artist: A
+Title: x
+Pos: 0
+Id: 1
+file: b.flac
+Pos: 1
+Id: 2
+OK
+"[..];
+        let mut res = Response::new(r);
+        block_on(async {
+            let record = res.record(&["file"]).await.unwrap().unwrap();
+            assert_eq!(record.len(), 5);
+            assert_eq!(record[0], ("file".into(), "a.flac".into()));
+            assert_eq!(record[4], ("Id".into(), "1".into()));
+            let record = res.record(&["file"]).await.unwrap().unwrap();
+            assert_eq!(record.len(), 3);
+            assert_eq!(record[0], ("file".into(), "b.flac".into()));
+            assert!(res.record(&["file"]).await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn binary() {
+        let r = &mut &b"size: 6\ntype: image/png\nbinary: 3\n\x00\n\xff\nOK\n"[..];
+        let mut res = Response::new(r);
+        block_on(async {
+            assert_eq!(res.next().await.unwrap(), Some(("size", "6")));
+            assert_eq!(res.next().await.unwrap(), Some(("type", "image/png")));
+            assert_eq!(res.next().await.unwrap(), Some(("binary", "3")));
+            assert_eq!(res.binary(), b"\x00\n\xff");
+            assert_eq!(res.next().await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn ack() {
+        let r = &mut &b"list_OK\nACK [50@1] {play} No such song\n"[..];
+        let mut res = Response::new(r);
+        block_on(async {
+            assert_eq!(res.next().await.unwrap(), Some(("list_OK", "")));
+            let e = res.next().await.unwrap_err();
+            let e = e.downcast_ref::<MpdError>().unwrap();
+            assert_eq!(e.code, 50);
+            assert_eq!(e.command_list_num, 1);
+            assert_eq!(e.current_command, "play");
+            assert_eq!(e.message, "No such song");
+            assert_eq!(res.next().await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn connection_closed() {
+        let r = &mut &b"volume: 42\nrepe"[..];
+        let mut res = Response::new(r);
+        block_on(async {
+            assert_eq!(res.next().await.unwrap(), Some(("volume", "42")));
+            assert!(res.next().await.is_err());
+        });
+    }
+}